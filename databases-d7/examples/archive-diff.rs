@@ -0,0 +1,137 @@
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use makaikit_databases_d7::diff::{diff_database_by_name, RecordDiff};
+use makaikit_vfs::EntryDiff;
+
+/// Lists the differences between two FAFULLFS or DSARC FL archives.
+#[derive(Debug, Parser)]
+struct Args {
+    old: PathBuf,
+    new: PathBuf,
+    /// Also list the records that changed in modified data/database/*.dat
+    /// entries
+    #[arg(short, long)]
+    databases: bool,
+}
+
+fn open(path: &Path) -> anyhow::Result<(BufReader<File>, [u8; 8])> {
+    let mut file = BufReader::new(
+        File::open(path).with_context(|| format!("Unable to open {}", path.display()))?,
+    );
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    file.rewind()?;
+    Ok((file, magic))
+}
+
+fn print_entry(change: char, path: &CStr) {
+    println!("{} {}", change, path.to_string_lossy());
+}
+
+fn print_records(diffs: &[RecordDiff]) {
+    for diff in diffs {
+        match diff {
+            RecordDiff::Added { id, enum_name } => println!("    + {id} {enum_name}"),
+            RecordDiff::Removed { id, enum_name } => println!("    - {id} {enum_name}"),
+            RecordDiff::Modified {
+                id,
+                enum_name,
+                fields,
+            } => println!("    M {id} {enum_name}: {}", fields.join(", ")),
+        }
+    }
+}
+
+fn diff_fafullfs(
+    old: BufReader<File>,
+    new: BufReader<File>,
+    databases: bool,
+) -> anyhow::Result<()> {
+    use makaikit_fafullfs::Archive;
+
+    let mut old = Archive::open(old).context("Unable to open old archive")?;
+    let mut new = Archive::open(new).context("Unable to open new archive")?;
+    for diff in makaikit_vfs::diff(&mut old, &mut new).context("Unable to compare archives")? {
+        match diff {
+            EntryDiff::Added { ref path, .. } => print_entry('A', path),
+            EntryDiff::Removed { ref path, .. } => print_entry('D', path),
+            EntryDiff::Modified {
+                ref path,
+                old_index,
+                new_index,
+            } => {
+                print_entry('M', path);
+                if !databases {
+                    continue;
+                }
+
+                let Some(name) = path
+                    .to_str()
+                    .ok()
+                    .and_then(|p| p.strip_prefix("data/database/"))
+                    .and_then(|p| p.strip_suffix(".dat"))
+                else {
+                    continue;
+                };
+
+                let mut old_bytes = Vec::new();
+                old.get_file(old_index)
+                    .unwrap()?
+                    .read_to_end(&mut old_bytes)?;
+                let mut new_bytes = Vec::new();
+                new.get_file(new_index)
+                    .unwrap()?
+                    .read_to_end(&mut new_bytes)?;
+                match diff_database_by_name(name, &old_bytes[..], &new_bytes[..]) {
+                    None => println!("    (unknown database)"),
+                    Some(record_diffs) => print_records(
+                        &record_diffs
+                            .with_context(|| format!("Unable to decode database {name}"))?,
+                    ),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff_dsarcfl(old: BufReader<File>, new: BufReader<File>) -> anyhow::Result<()> {
+    use makaikit_dsarcfl::Archive;
+
+    let mut old = Archive::open(old).context("Unable to open old archive")?;
+    let mut new = Archive::open(new).context("Unable to open new archive")?;
+    for diff in makaikit_vfs::diff(&mut old, &mut new).context("Unable to compare archives")? {
+        let change = match diff {
+            EntryDiff::Added { .. } => 'A',
+            EntryDiff::Removed { .. } => 'D',
+            EntryDiff::Modified { .. } => 'M',
+        };
+        print_entry(change, diff.path());
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let (old, old_magic) = open(&args.old)?;
+    let (new, new_magic) = open(&args.new)?;
+    anyhow::ensure!(
+        old_magic == new_magic,
+        "The archives are of different formats"
+    );
+
+    match &old_magic {
+        b"FAFULLFS" => diff_fafullfs(old, new, args.databases),
+        b"DSARC FL" => diff_dsarcfl(old, new),
+        _ => anyhow::bail!("Unrecognised archive format"),
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use makaikit_databases_d7::{
+    bgmcheck::{self, BgmFiles, BgmIssue},
+    modfile, BgmData,
+};
+use makaikit_databases_serde::DatabaseRecord;
+use makaikit_fafullfs::Archive;
+use makaikit_vfs::{DirVfs, OverlayVfs, Vfs};
+
+/// Reports the BGM records whose sample rate or loop points don't match the
+/// tracks they play.
+#[derive(Debug, Parser)]
+struct Args {
+    /// The game's data.dat
+    path: PathBuf,
+    /// A mod directory, whose files replace those in data.dat and whose
+    /// databases/bgm files change the records as the mod loader applies them
+    #[arg(short, long)]
+    mod_dir: Option<PathBuf>,
+}
+
+/// Applies the mod's `databases/bgm` files the way the mod loader does,
+/// reporting those it would skip.
+fn apply_mod_records(mod_dir: &Path, records: &mut BTreeMap<i32, BgmData>) -> anyhow::Result<()> {
+    modfile::apply_mod_files(records, &mut DirVfs::new(mod_dir), "bgm", |path, result| {
+        if let Err(e) = result {
+            println!(
+                "{}: skipped by the mod loader: {:#}",
+                path,
+                anyhow::Error::from(e)
+            );
+        }
+    })?;
+    Ok(())
+}
+
+fn describe(issue: &BgmIssue) -> String {
+    match issue {
+        BgmIssue::SampleRateMismatch { record, audio } => {
+            format!("sample rate is {} but the track's is {}", record, audio)
+        }
+        BgmIssue::LoopOutOfRange {
+            loop_start,
+            loop_end,
+            samples,
+        } => format!(
+            "loop {}..{} is not within the track's {} samples",
+            loop_start, loop_end, samples
+        ),
+        BgmIssue::LoopMismatch {
+            loop_start,
+            loop_end,
+            audio_loop_start,
+            audio_loop_end,
+        } => format!(
+            "loop {}..{} but the track loops {}..{}",
+            loop_start, loop_end, audio_loop_start, audio_loop_end
+        ),
+    }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+
+    let mut archive = Archive::open(BufReader::new(
+        File::open(&args.path)
+            .with_context(|| format!("Unable to open {}", args.path.display()))?,
+    ))?;
+    let mut records: BTreeMap<i32, BgmData> =
+        makaikit_databases_serde::decode_database::<_, BgmData>(
+            archive
+                .open("data/database/bgm.dat")
+                .context("Unable to open data/database/bgm.dat")?,
+        )
+        .context("Unable to decode data/database/bgm.dat")?
+        .into_iter()
+        .map(|record| (record.database_id(), record))
+        .collect();
+    if let Some(ref mod_dir) = args.mod_dir {
+        apply_mod_records(mod_dir, &mut records).context("Unable to read mod databases")?;
+    }
+
+    let mut files = OverlayVfs::new();
+    if let Some(ref mod_dir) = args.mod_dir {
+        files.push(DirVfs::new(mod_dir.join("files")));
+    }
+    files.push(archive);
+    let index = BgmFiles::index(&files).context("Unable to list files")?;
+
+    let mut problems = 0;
+    for record in records.values() {
+        let label = format!("{} ({})", record.id, record.enum_name);
+        let Some(path) = index.find(record) else {
+            println!(
+                "{}: no file found for {}/{}",
+                label, record.folder_name, record.file_name
+            );
+            problems += 1;
+            continue;
+        };
+        let audio = match bgmcheck::read_audio(&mut files, path) {
+            Ok(audio) => audio,
+            Err(e) => {
+                println!("{}: unable to read {}: {}", label, path, e);
+                problems += 1;
+                continue;
+            }
+        };
+
+        let issues = bgmcheck::check(record, &audio);
+        for issue in issues.iter() {
+            println!("{}: {}: {}", label, path, describe(issue));
+        }
+        if !issues.is_empty() {
+            problems += 1;
+        }
+    }
+
+    println!("{} tracks with problems", problems);
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Checks [`BgmData`] records against the NLSD files they play.
+//!
+//! The loop points of a record are taken to be sample positions from the
+//! start of the track, and the track to be `{folder_name}/{file_name}` with
+//! any extension, somewhere in the archive. Neither has been confirmed
+//! against retail data, so the issues found are only worth reporting.
+
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read},
+};
+
+use makaikit_nlsd::{NlsdRead, ReadError};
+use makaikit_vfs::Vfs;
+use makaikit_ykcmp::MaybeCompressed;
+
+use crate::BgmData;
+
+/// What an NLSD file holds, going by its sections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BgmAudio {
+    pub sample_rate: u32,
+    pub samples: u64,
+    pub loop_start: u64,
+    pub loop_end: u64,
+}
+
+/// A way in which a record disagrees with its track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BgmIssue {
+    SampleRateMismatch {
+        record: i32,
+        audio: u32,
+    },
+    /// The record's loop is empty or ends past the end of the track.
+    LoopOutOfRange {
+        loop_start: i32,
+        loop_end: i32,
+        samples: u64,
+    },
+    /// The record's loop is not where the track's loop section is.
+    LoopMismatch {
+        loop_start: i32,
+        loop_end: i32,
+        audio_loop_start: u64,
+        audio_loop_end: u64,
+    },
+}
+
+/// The files of a [`Vfs`] indexed by file stem, for finding the track a
+/// record names.
+pub struct BgmFiles {
+    by_stem: HashMap<String, Vec<String>>,
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.split('.').next().unwrap_or(name)
+}
+
+impl BgmFiles {
+    pub fn index<V: Vfs + ?Sized>(vfs: &V) -> io::Result<Self> {
+        let mut by_stem: HashMap<String, Vec<String>> = HashMap::new();
+        for path in vfs.list("")? {
+            by_stem
+                .entry(file_stem(&path).to_lowercase())
+                .or_default()
+                .push(path);
+        }
+        Ok(BgmFiles { by_stem })
+    }
+
+    /// The path of the track `record` plays. Files in a directory named
+    /// after the record's folder are preferred, then `.nlsd` files.
+    pub fn find(&self, record: &BgmData) -> Option<&str> {
+        let candidates = self
+            .by_stem
+            .get(&file_stem(&record.file_name).to_lowercase())?;
+        let folder = record.folder_name.trim_matches('/').to_lowercase();
+        candidates
+            .iter()
+            .max_by_key(|path| {
+                let path = path.to_lowercase();
+                let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+                let in_folder =
+                    !folder.is_empty() && (dir == folder || dir.ends_with(&format!("/{}", folder)));
+                (in_folder, path.ends_with(".nlsd"))
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Reads the NLSD file at `path`, which may be YKCMP compressed.
+pub fn read_audio<V: Vfs + ?Sized>(vfs: &mut V, path: &str) -> Result<BgmAudio, ReadError> {
+    let mut data = Vec::new();
+    MaybeCompressed::new(vfs.open(path)?)
+        .map_err(io::Error::other)?
+        .read_to_end(&mut data)?;
+
+    let mut nlsd = NlsdRead::open(Cursor::new(data))?;
+    let loop_samples = nlsd.loop_samples()?;
+    Ok(BgmAudio {
+        sample_rate: u32::from(nlsd.sample_rate()),
+        samples: u64::from(nlsd.samples()),
+        loop_start: loop_samples.start,
+        loop_end: loop_samples.end,
+    })
+}
+
+pub fn check(record: &BgmData, audio: &BgmAudio) -> Vec<BgmIssue> {
+    let mut issues = Vec::new();
+    if i64::from(record.sample_rate) != i64::from(audio.sample_rate) {
+        issues.push(BgmIssue::SampleRateMismatch {
+            record: record.sample_rate,
+            audio: audio.sample_rate,
+        });
+    }
+    let in_range = 0 <= record.loop_start
+        && record.loop_start < record.loop_end
+        && record.loop_end as u64 <= audio.samples;
+    if !in_range {
+        issues.push(BgmIssue::LoopOutOfRange {
+            loop_start: record.loop_start,
+            loop_end: record.loop_end,
+            samples: audio.samples,
+        });
+    } else if (record.loop_start as u64, record.loop_end as u64)
+        != (audio.loop_start, audio.loop_end)
+    {
+        issues.push(BgmIssue::LoopMismatch {
+            loop_start: record.loop_start,
+            loop_end: record.loop_end,
+            audio_loop_start: audio.loop_start,
+            audio_loop_end: audio.loop_end,
+        });
+    }
+    issues
+}
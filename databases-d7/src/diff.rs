@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, io::Read};
+
+use makaikit_databases_serde::{DatabaseRecord, DeserializerError};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A difference in a record between two versions of a database, matched by
+/// ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordDiff {
+    Added {
+        id: i32,
+        enum_name: String,
+    },
+    Removed {
+        id: i32,
+        enum_name: String,
+    },
+    /// Lists the names of the fields that changed, as they are serialized.
+    Modified {
+        id: i32,
+        enum_name: String,
+        fields: Vec<String>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum DiffError {
+    #[error("Unable to decode database")]
+    Decode(#[from] DeserializerError),
+
+    #[error("Unable to serialize record {id}")]
+    Serialize {
+        id: i32,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Record {id} does not serialize to a JSON object")]
+    NotAnObject { id: i32 },
+}
+
+fn to_fields<T: DatabaseRecord + Serialize>(
+    record: &T,
+) -> Result<serde_json::Map<String, serde_json::Value>, DiffError> {
+    let id = record.database_id();
+    match serde_json::to_value(record) {
+        Ok(serde_json::Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err(DiffError::NotAnObject { id }),
+        Err(source) => Err(DiffError::Serialize { id, source }),
+    }
+}
+
+fn by_id<T: DatabaseRecord>(records: &[T]) -> BTreeMap<i32, &T> {
+    let mut map = BTreeMap::new();
+    for record in records {
+        map.entry(record.database_id()).or_insert(record);
+    }
+    map
+}
+
+/// Lists the records added, removed and modified from `old` to `new`,
+/// ordered by ID.
+pub fn diff_records<T: DatabaseRecord + Serialize>(
+    old: &[T],
+    new: &[T],
+) -> Result<Vec<RecordDiff>, DiffError> {
+    let old = by_id(old);
+    let new = by_id(new);
+
+    let mut diffs = Vec::new();
+    for (&id, old_record) in old.iter() {
+        let Some(new_record) = new.get(&id) else {
+            diffs.push(RecordDiff::Removed {
+                id,
+                enum_name: old_record.database_enum_name().to_owned(),
+            });
+            continue;
+        };
+
+        let old_fields = to_fields(*old_record)?;
+        let new_fields = to_fields(*new_record)?;
+        let fields: Vec<String> = new_fields
+            .iter()
+            .filter(|&(name, value)| old_fields.get(name) != Some(value))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if !fields.is_empty() {
+            diffs.push(RecordDiff::Modified {
+                id,
+                enum_name: new_record.database_enum_name().to_owned(),
+                fields,
+            });
+        }
+    }
+    for (&id, new_record) in new.iter() {
+        if !old.contains_key(&id) {
+            diffs.push(RecordDiff::Added {
+                id,
+                enum_name: new_record.database_enum_name().to_owned(),
+            });
+        }
+    }
+
+    diffs.sort_by_key(|diff| match diff {
+        RecordDiff::Added { id, .. }
+        | RecordDiff::Removed { id, .. }
+        | RecordDiff::Modified { id, .. } => *id,
+    });
+    Ok(diffs)
+}
+
+/// Decodes two versions of a database file and lists the differences in
+/// their records.
+pub fn diff_database<T, R1, R2>(old: R1, new: R2) -> Result<Vec<RecordDiff>, DiffError>
+where
+    T: DatabaseRecord + DeserializeOwned + Serialize,
+    R1: Read,
+    R2: Read,
+{
+    let old = makaikit_databases_serde::decode_database::<_, T>(old)?;
+    let new = makaikit_databases_serde::decode_database::<_, T>(new)?;
+    diff_records(&old, &new)
+}
+
+/// Like [`diff_database`], picking the schema from the name of the database,
+/// as in `data/database/{name}.dat`. Returns `None` for unknown databases.
+pub fn diff_database_by_name<R1: Read, R2: Read>(
+    name: &str,
+    old: R1,
+    new: R2,
+) -> Option<Result<Vec<RecordDiff>, DiffError>> {
+    macro_rules! dispatch {
+        ($($name:literal => $ty:ty,)*) => {
+            match name {
+                $($name => Some(diff_database::<$ty, _, _>(old, new)),)*
+                _ => None,
+            }
+        };
+    }
+
+    crate::for_each_database!(dispatch)
+}
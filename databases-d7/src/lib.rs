@@ -13,12 +13,14 @@
 pub mod area;
 pub mod battleflag;
 pub mod bgm;
+pub mod bgmcheck;
 pub mod bu;
 pub mod characlass;
 pub mod character;
 pub mod charafeature;
 pub mod charazukan;
 pub mod cheatsetting;
+pub mod diff;
 pub mod doping;
 pub mod drink;
 pub mod dungeon;
@@ -33,6 +35,7 @@
 pub mod itemstrengthen;
 pub mod itemtype;
 pub mod job;
+pub mod modfile;
 pub mod stage;
 pub mod string;
 pub mod wish;
@@ -74,6 +77,53 @@
 pub use self::string::StringData;
 pub use self::wish::WishData;
 
+/// Invokes the macro `$callback` with every database, as a list of
+/// `"name" => Type,` pairs where `name` is the stem of
+/// `data/database/{name}.dat`.
+#[macro_export]
+macro_rules! for_each_database {
+    ($callback:ident) => {
+        $callback! {
+            "act" => $crate::ActData,
+            "acteffect" => $crate::ActEffectData,
+            "actfeature" => $crate::ActFeatureData,
+            "actlearn" => $crate::ActLearnData,
+            "actmap" => $crate::ActMapData,
+            "ai" => $crate::AiData,
+            "aiparts" => $crate::AiPartsData,
+            "anime" => $crate::AnimeData,
+            "animebank" => $crate::AnimeBankData,
+            "archive" => $crate::ArchiveData,
+            "area" => $crate::AreaData,
+            "battleflag" => $crate::BattleFlagData,
+            "bgm" => $crate::BgmData,
+            "bu" => $crate::BuData,
+            "characlass" => $crate::CharaClassData,
+            "character" => $crate::CharaData,
+            "charafeature" => $crate::CharaFeatureData,
+            "charazukan" => $crate::CharaZukanData,
+            "cheatsetting" => $crate::CheatSettingData,
+            "doping" => $crate::DopingData,
+            "drink" => $crate::DrinkData,
+            "dungeon" => $crate::DungeonData,
+            "evility" => $crate::EvilityData,
+            "hospitalgacha" => $crate::HospitalGachaData,
+            "innocentaffinity" => $crate::InnocentAffinityData,
+            "innocent" => $crate::InnocentData,
+            "item" => $crate::ItemData,
+            "itemcustom" => $crate::ItemCustomData,
+            "itemfeature" => $crate::ItemFeatureData,
+            "itemshoplineup" => $crate::ItemShopLineupData,
+            "itemstrengthen" => $crate::ItemStrengthenData,
+            "itemtype" => $crate::ItemTypeData,
+            "job" => $crate::JobData,
+            "stage" => $crate::StageData,
+            "string" => $crate::StringData,
+            "wish" => $crate::WishData,
+        }
+    };
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CmlString {
@@ -0,0 +1,179 @@
+//! The files a mod places in `databases/{name}` to change records of a
+//! database, as the mod loader applies them.
+//!
+//! `{record}.json` files replace a record outright. `{record}.merge.json`
+//! files are JSON merge patches and `{record}.patch.json` files are JSON
+//! patches of the record named by `{record}`, which is its ID followed by
+//! `_` and anything, or its enum name.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read},
+    str::FromStr,
+};
+
+use makaikit_databases_serde::DatabaseRecord;
+use makaikit_vfs::Vfs;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The record a patch file names.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordIdentifier {
+    Id(i32),
+    EnumName(String),
+}
+
+/// How a mod database file changes its record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModFileKind {
+    Replacement,
+    MergePatch,
+    JsonPatch,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ModFileError {
+    #[error("File stem {0} does not identify a database record")]
+    InvalidStem(String),
+
+    #[error("There is no source record for {0:?}")]
+    NoSourceRecord(RecordIdentifier),
+
+    #[error("Unable to convert record {id} to JSON")]
+    ToJson {
+        id: i32,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Not a proper JSON file")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("JSON patch application failed")]
+    Patch(#[from] json_patch::PatchError),
+
+    #[error("Not a proper record after patching")]
+    FromJson(#[source] serde_json::Error),
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+pub fn parse_file_stem(base: &str) -> Option<RecordIdentifier> {
+    if base.is_empty() {
+        return None;
+    }
+    match base.split_once('_') {
+        Some((id, _)) => match i32::from_str(id) {
+            Ok(id) => Some(RecordIdentifier::Id(id)),
+            Err(_) => Some(RecordIdentifier::EnumName(base.to_owned())),
+        },
+        None => Some(RecordIdentifier::EnumName(base.to_owned())),
+    }
+}
+
+/// The kind of mod database file `file_name` is, if it is one.
+pub fn file_kind(file_name: &str) -> Option<ModFileKind> {
+    if file_name.ends_with(".patch.json") {
+        Some(ModFileKind::JsonPatch)
+    } else if file_name.ends_with(".merge.json") {
+        Some(ModFileKind::MergePatch)
+    } else if file_name.ends_with(".json") {
+        Some(ModFileKind::Replacement)
+    } else {
+        None
+    }
+}
+
+/// The record a patch with file stem `stem` applies to, as JSON.
+fn patched_record<T>(
+    records: &BTreeMap<i32, T>,
+    stem: &str,
+) -> Result<serde_json::Value, ModFileError>
+where
+    T: DatabaseRecord + Serialize,
+{
+    let identifier =
+        parse_file_stem(stem).ok_or_else(|| ModFileError::InvalidStem(stem.to_owned()))?;
+    let record = match identifier {
+        RecordIdentifier::Id(id) => records.get(&id),
+        RecordIdentifier::EnumName(ref name) => records
+            .values()
+            .find(|record| record.database_enum_name() == name),
+    }
+    .ok_or(ModFileError::NoSourceRecord(identifier))?;
+    serde_json::to_value(record).map_err(|source| ModFileError::ToJson {
+        id: record.database_id(),
+        source,
+    })
+}
+
+/// Applies the mod database file named `file_name` to `records`, keyed by
+/// ID. Returns what kind of file it was, or `None` if it isn't one and was
+/// left alone. `records` is unchanged if this fails.
+pub fn apply_mod_file<T, R>(
+    records: &mut BTreeMap<i32, T>,
+    file_name: &str,
+    mut file: R,
+) -> Result<Option<ModFileKind>, ModFileError>
+where
+    T: DatabaseRecord + Serialize + DeserializeOwned,
+    R: Read,
+{
+    let Some(kind) = file_kind(file_name) else {
+        return Ok(None);
+    };
+    let record: T = match kind {
+        ModFileKind::JsonPatch => {
+            let stem = file_name.strip_suffix(".patch.json").unwrap();
+            let mut value = patched_record(records, stem)?;
+            let patch: json_patch::Patch =
+                serde_json::from_reader(&mut file).map_err(ModFileError::Parse)?;
+            json_patch::patch(&mut value, &patch.0)?;
+            serde_json::from_value(value).map_err(ModFileError::FromJson)?
+        }
+        ModFileKind::MergePatch => {
+            let stem = file_name.strip_suffix(".merge.json").unwrap();
+            let mut value = patched_record(records, stem)?;
+            let patch: serde_json::Value =
+                serde_json::from_reader(&mut file).map_err(ModFileError::Parse)?;
+            json_patch::merge(&mut value, &patch);
+            serde_json::from_value(value).map_err(ModFileError::FromJson)?
+        }
+        ModFileKind::Replacement => {
+            serde_json::from_reader(&mut file).map_err(ModFileError::Parse)?
+        }
+    };
+    records.insert(record.database_id(), record);
+    Ok(Some(kind))
+}
+
+/// Applies the mod database files directly in `databases/{name}` of a mod's
+/// directory to `records`, in path order. `report` is given the path and
+/// outcome of each.
+pub fn apply_mod_files<T, V>(
+    records: &mut BTreeMap<i32, T>,
+    mod_vfs: &mut V,
+    name: &str,
+    mut report: impl FnMut(&str, Result<ModFileKind, ModFileError>),
+) -> io::Result<()>
+where
+    T: DatabaseRecord + Serialize + DeserializeOwned,
+    V: Vfs + ?Sized,
+{
+    let database_dir = format!("databases/{name}");
+    for path in mod_vfs.list(&database_dir)? {
+        let file_name = &path[database_dir.len() + 1..];
+        if file_name.contains('/') || file_kind(file_name).is_none() {
+            continue;
+        }
+        let result = mod_vfs
+            .open(&path)
+            .map_err(ModFileError::from)
+            .and_then(|file| apply_mod_file(records, file_name, file))
+            .map(Option::unwrap);
+        report(&path, result);
+    }
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    io::{self, Read, Seek, Write},
+};
+
+use crate::{Archive, ArchiveStreamWriter, ReadError, WriteError};
+
+/// Records changes to an [`Archive`] and writes them out as a new archive.
+///
+/// Files keep their original order, appended files go at the end, and
+/// unchanged files are copied verbatim from the source. A name the source
+/// has more than once refers to all of its files.
+pub struct ArchiveEditor<'a, R: Read + Seek> {
+    source: &'a mut Archive<R>,
+    entries: Vec<Option<EditEntry>>,
+    names: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+struct EditEntry {
+    name: CString,
+    data: EntryData,
+}
+
+enum EntryData {
+    Original(usize),
+    Replaced(Vec<u8>),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum EditError {
+    #[error("No file named {0} in the archive")]
+    NotFound(String),
+
+    #[error("A file named {0} is already in the archive")]
+    AlreadyExists(String),
+
+    #[error("File name {0:?} is invalid")]
+    InvalidName(String),
+
+    #[error("DSARC FL read error")]
+    Read(#[from] ReadError),
+
+    #[error("DSARC FL write error")]
+    Write(#[from] WriteError),
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+fn check_name(name: &str) -> Result<CString, EditError> {
+    match CString::new(name) {
+        Ok(cstring) if !name.is_empty() && name.len() < 0x74 => Ok(cstring),
+        _ => Err(EditError::InvalidName(name.to_owned())),
+    }
+}
+
+impl<'a, R: Read + Seek> ArchiveEditor<'a, R> {
+    /// Fails if a file in `source` has an invalid name, as it couldn't be
+    /// written back out.
+    pub fn new(source: &'a mut Archive<R>) -> Result<Self, EditError> {
+        let mut entries = Vec::with_capacity(source.len());
+        let mut names = HashMap::with_capacity(source.len());
+        for (index, entry) in source.entries().enumerate() {
+            let name = entry.name()?;
+            names
+                .entry(name.to_bytes().to_vec())
+                .or_insert_with(Vec::new)
+                .push(index);
+            entries.push(Some(EditEntry {
+                name: name.to_owned(),
+                data: EntryData::Original(index),
+            }));
+        }
+
+        Ok(ArchiveEditor {
+            source,
+            entries,
+            names,
+        })
+    }
+
+    fn take_slots(&mut self, name: &str) -> Result<Vec<usize>, EditError> {
+        self.names
+            .remove(name.as_bytes())
+            .ok_or_else(|| EditError::NotFound(name.to_owned()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(name.as_bytes())
+    }
+
+    /// Names of the files that will be written, in order.
+    pub fn names(&self) -> impl Iterator<Item = &CStr> {
+        self.entries.iter().flatten().map(|e| e.name.as_c_str())
+    }
+
+    pub fn replace(&mut self, name: &str, data: Vec<u8>) -> Result<(), EditError> {
+        let slots = self
+            .names
+            .get(name.as_bytes())
+            .ok_or_else(|| EditError::NotFound(name.to_owned()))?;
+        for &slot in slots.iter() {
+            self.entries[slot].as_mut().unwrap().data = EntryData::Replaced(data.clone());
+        }
+        Ok(())
+    }
+
+    pub fn append(&mut self, name: &str, data: Vec<u8>) -> Result<(), EditError> {
+        if self.contains(name) {
+            return Err(EditError::AlreadyExists(name.to_owned()));
+        }
+        let name_cstring = check_name(name)?;
+        self.names
+            .insert(name.as_bytes().to_vec(), vec![self.entries.len()]);
+        self.entries.push(Some(EditEntry {
+            name: name_cstring,
+            data: EntryData::Replaced(data),
+        }));
+        Ok(())
+    }
+
+    /// Replaces the file named `name`, or appends it if there is none.
+    pub fn put(&mut self, name: &str, data: Vec<u8>) -> Result<(), EditError> {
+        if self.contains(name) {
+            self.replace(name, data)
+        } else {
+            self.append(name, data)
+        }
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), EditError> {
+        if !self.contains(from) {
+            return Err(EditError::NotFound(from.to_owned()));
+        }
+        if self.contains(to) {
+            return Err(EditError::AlreadyExists(to.to_owned()));
+        }
+        let to_cstring = check_name(to)?;
+        let slots = self.take_slots(from)?;
+        for &slot in slots.iter() {
+            self.entries[slot].as_mut().unwrap().name = to_cstring.clone();
+        }
+        self.names.insert(to.as_bytes().to_vec(), slots);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), EditError> {
+        for slot in self.take_slots(name)? {
+            self.entries[slot] = None;
+        }
+        Ok(())
+    }
+
+    /// Writes the edited archive to `out`.
+    pub fn write_to<W: Write + Seek>(self, out: W) -> Result<W, EditError> {
+        let count = self.entries.iter().flatten().count();
+        let mut writer = ArchiveStreamWriter::new(out, count)?;
+
+        for entry in self.entries.iter().flatten() {
+            let mut file_writer = writer.file(&entry.name)?;
+            match entry.data {
+                EntryData::Original(index) => {
+                    let mut file = self.source.get_file(index).unwrap()?;
+                    io::copy(&mut file, &mut file_writer)?;
+                }
+                EntryData::Replaced(ref data) => {
+                    file_writer.write_all(&data[..])?;
+                }
+            }
+        }
+
+        Ok(writer.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::ArchiveWriter;
+
+    fn archive(files: &[(&str, &[u8])]) -> Archive<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new();
+        for &(name, data) in files {
+            let name = CString::new(name).unwrap();
+            writer.file(&name).unwrap().write_all(data).unwrap();
+        }
+        Archive::open(Cursor::new(writer.finish().unwrap())).unwrap()
+    }
+
+    /// The names and contents of an archive's files, in order.
+    fn contents<R: Read + Seek>(archive: &mut Archive<R>) -> Vec<(String, Vec<u8>)> {
+        (0..archive.len())
+            .map(|index| {
+                let mut file = archive.get_file(index).unwrap().unwrap();
+                let name = file.name().to_str().unwrap().to_owned();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).unwrap();
+                (name, data)
+            })
+            .collect()
+    }
+
+    fn edit(
+        source: &[(&str, &[u8])],
+        f: impl FnOnce(&mut ArchiveEditor<'_, Cursor<Vec<u8>>>),
+    ) -> Vec<(String, Vec<u8>)> {
+        let mut source = archive(source);
+        let mut editor = ArchiveEditor::new(&mut source).unwrap();
+        f(&mut editor);
+        let out = editor.write_to(Cursor::new(Vec::new())).unwrap();
+        contents(&mut Archive::open(Cursor::new(out.into_inner())).unwrap())
+    }
+
+    fn owned(files: &[(&str, &[u8])]) -> Vec<(String, Vec<u8>)> {
+        files
+            .iter()
+            .map(|&(name, data)| (name.to_owned(), data.to_vec()))
+            .collect()
+    }
+
+    const SOURCE: &[(&str, &[u8])] = &[("a.lub", b"aaaa"), ("b.lub", b""), ("c.lub", b"cc")];
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(edit(SOURCE, |_| {}), owned(SOURCE));
+    }
+
+    #[test]
+    fn put_replaces_in_place() {
+        let result = edit(SOURCE, |editor| {
+            editor.put("b.lub", vec![7; 0x300]).unwrap();
+        });
+        assert_eq!(
+            result,
+            owned(&[("a.lub", b"aaaa"), ("b.lub", &[7; 0x300]), ("c.lub", b"cc")])
+        );
+    }
+
+    #[test]
+    fn put_and_append_add_at_end() {
+        let result = edit(SOURCE, |editor| {
+            editor.put("d.lub", b"dd".to_vec()).unwrap();
+            editor.append("e.lub", b"e".to_vec()).unwrap();
+            assert!(matches!(
+                editor.append("a.lub", Vec::new()),
+                Err(EditError::AlreadyExists(_))
+            ));
+            assert!(matches!(
+                editor.append("", Vec::new()),
+                Err(EditError::InvalidName(_))
+            ));
+            assert!(matches!(
+                editor.append(&"x".repeat(0x74), Vec::new()),
+                Err(EditError::InvalidName(_))
+            ));
+        });
+        assert_eq!(
+            result,
+            owned(&[
+                ("a.lub", b"aaaa"),
+                ("b.lub", b""),
+                ("c.lub", b"cc"),
+                ("d.lub", b"dd"),
+                ("e.lub", b"e"),
+            ])
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let result = edit(SOURCE, |editor| {
+            editor.rename("a.lub", "z.lub").unwrap();
+            assert!(matches!(
+                editor.rename("a.lub", "y.lub"),
+                Err(EditError::NotFound(_))
+            ));
+            assert!(matches!(
+                editor.rename("b.lub", "c.lub"),
+                Err(EditError::AlreadyExists(_))
+            ));
+            // the old name is free again
+            editor.append("a.lub", b"new".to_vec()).unwrap();
+        });
+        assert_eq!(
+            result,
+            owned(&[
+                ("z.lub", b"aaaa"),
+                ("b.lub", b""),
+                ("c.lub", b"cc"),
+                ("a.lub", b"new"),
+            ])
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let result = edit(SOURCE, |editor| {
+            editor.delete("a.lub").unwrap();
+            assert!(matches!(
+                editor.delete("a.lub"),
+                Err(EditError::NotFound(_))
+            ));
+            assert!(!editor.contains("a.lub"));
+            editor.put("a.lub", b"back".to_vec()).unwrap();
+        });
+        assert_eq!(
+            result,
+            owned(&[("b.lub", b""), ("c.lub", b"cc"), ("a.lub", b"back")])
+        );
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let source: &[(&str, &[u8])] = &[("dup", b"1"), ("a.lub", b"a"), ("dup", b"2")];
+
+        let replaced = edit(source, |editor| {
+            editor.replace("dup", b"x".to_vec()).unwrap();
+        });
+        assert_eq!(
+            replaced,
+            owned(&[("dup", b"x"), ("a.lub", b"a"), ("dup", b"x")])
+        );
+
+        let renamed = edit(source, |editor| {
+            editor.rename("dup", "one").unwrap();
+            assert!(!editor.contains("dup"));
+        });
+        assert_eq!(
+            renamed,
+            owned(&[("one", b"1"), ("a.lub", b"a"), ("one", b"2")])
+        );
+
+        let deleted = edit(source, |editor| {
+            editor.delete("dup").unwrap();
+            assert!(!editor.contains("dup"));
+        });
+        assert_eq!(deleted, owned(&[("a.lub", b"a")]));
+    }
+}
@@ -1,5 +1,9 @@
+mod edit;
 mod read;
 mod write;
 
-pub use self::read::{Archive, ArchiveFileAccess, ReadError};
-pub use self::write::{ArchiveFileWriter, ArchiveWriter, WriteError};
+pub use self::edit::{ArchiveEditor, EditError};
+pub use self::read::{Archive, ArchiveFileAccess, Entry, ReadError};
+pub use self::write::{
+    ArchiveFileWriter, ArchiveStreamFileWriter, ArchiveStreamWriter, ArchiveWriter, WriteError,
+};
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     ffi::CStr,
     io::{self, Read, Seek},
 };
@@ -7,6 +8,7 @@
 pub struct Archive<R: Read + Seek> {
     inner: R,
     files: Vec<ArchiveFile>,
+    names: HashMap<Box<[u8]>, usize>,
 }
 
 #[derive(Debug)]
@@ -16,6 +18,14 @@ struct ArchiveFile {
     offset: u32,
 }
 
+/// Metadata of a file in an [`Archive`].
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    name: Option<&'a CStr>,
+    size: u32,
+    offset: u32,
+}
+
 pub struct ArchiveFileAccess<'a, R: Read + Seek> {
     name: &'a CStr,
     size: u32,
@@ -37,12 +47,45 @@ pub enum ReadError {
     #[error("The name of a file in this archive is invalid")]
     InvalidName,
 
+    #[error("The file table extends past the end of the archive")]
+    TableOutOfBounds,
+
+    #[error("File {index} lies outside of the archive's data")]
+    FileOutOfBounds { index: usize },
+
+    #[error("Files {first} and {second} overlap")]
+    FilesOverlap { first: usize, second: usize },
+
     #[error("IO error")]
     Io(#[from] io::Error),
 }
 
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+impl ArchiveFile {
+    fn name(&self) -> Option<&CStr> {
+        match CStr::from_bytes_until_nul(&self.name_buf[..]) {
+            Ok(name) if !name.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    fn entry(&self) -> Entry<'_> {
+        Entry {
+            name: self.name(),
+            size: self.size,
+            offset: self.offset,
+        }
+    }
+}
+
 impl<R: Read + Seek> Archive<R> {
     pub fn open(mut inner: R) -> Result<Self, ReadError> {
+        let stream_len = inner.seek(io::SeekFrom::End(0))?;
+        inner.seek(io::SeekFrom::Start(0))?;
+
         let mut header_buf = [0u8; 16];
         inner.read_exact(&mut header_buf[..])?;
 
@@ -50,26 +93,29 @@ pub fn open(mut inner: R) -> Result<Self, ReadError> {
             return Err(ReadError::InvalidMagic);
         }
 
-        let files_count =
-            u32::from_le_bytes(unsafe { *(&header_buf[8..12] as *const [u8] as *const [u8; 4]) });
-
-        if files_count > usize::MAX as u32 {
+        let files_count = read_u32(&header_buf, 8);
+        if files_count as u64 > usize::MAX as u64 {
             return Err(ReadError::TooLarge);
         }
+        let table_end = 0x10 + files_count as u64 * 0x80;
+        if table_end > stream_len {
+            return Err(ReadError::TableOutOfBounds);
+        }
 
         let mut files = Vec::with_capacity(files_count as usize);
 
-        for _ in 0..files_count {
+        for index in 0..files_count as usize {
             let mut file_hdr = [0u8; 0x80];
-            inner.read_exact(&mut file_hdr[..0x80])?;
+            inner.read_exact(&mut file_hdr[..])?;
             let mut name_buf = [0u8; 0x74];
-            (&mut name_buf[..]).copy_from_slice(&file_hdr[..0x74]);
-            let size = u32::from_le_bytes(unsafe {
-                *(&file_hdr[0x74..0x78] as *const [u8] as *const [u8; 4])
-            });
-            let offset = u32::from_le_bytes(unsafe {
-                *(&file_hdr[0x78..0x7A] as *const [u8] as *const [u8; 4])
-            });
+            name_buf.copy_from_slice(&file_hdr[..0x74]);
+            let size = read_u32(&file_hdr, 0x74);
+            let offset = read_u32(&file_hdr, 0x78);
+
+            let end = offset as u64 + size as u64;
+            if (size != 0 && (offset as u64) < table_end) || end > stream_len {
+                return Err(ReadError::FileOutOfBounds { index });
+            }
 
             files.push(ArchiveFile {
                 name_buf,
@@ -78,34 +124,65 @@ pub fn open(mut inner: R) -> Result<Self, ReadError> {
             });
         }
 
-        Ok(Archive { inner, files })
+        let mut by_offset: Vec<usize> = (0..files.len()).filter(|&i| files[i].size != 0).collect();
+        by_offset.sort_by_key(|&i| files[i].offset);
+        for pair in by_offset.windows(2) {
+            let (a, b) = (&files[pair[0]], &files[pair[1]]);
+            if a.offset as u64 + a.size as u64 > b.offset as u64 {
+                return Err(ReadError::FilesOverlap {
+                    first: pair[0].min(pair[1]),
+                    second: pair[0].max(pair[1]),
+                });
+            }
+        }
+
+        let mut names = HashMap::with_capacity(files.len());
+        for (index, file) in files.iter().enumerate() {
+            // files with invalid names can't be found, only reported when
+            // they are accessed
+            if let Some(name) = file.name() {
+                names.entry(name.to_bytes().into()).or_insert(index);
+            }
+        }
+
+        Ok(Archive {
+            inner,
+            files,
+            names,
+        })
     }
 
     pub fn len(&self) -> usize {
         self.files.len()
     }
 
-    pub fn get_file<'a>(
-        &'a mut self,
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the index of the first file named `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.get(name.as_bytes()).copied()
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = Entry<'_>> {
+        self.files.iter().map(ArchiveFile::entry)
+    }
+
+    pub fn entry(&self, index: usize) -> Option<Entry<'_>> {
+        self.files.get(index).map(ArchiveFile::entry)
+    }
+
+    pub fn get_file(
+        &mut self,
         index: usize,
-    ) -> Option<Result<ArchiveFileAccess<'a, R>, ReadError>> {
+    ) -> Option<Result<ArchiveFileAccess<'_, R>, ReadError>> {
         self.files.get(index).map(|file| {
-            let first_null = match file.name_buf.iter().position(|&x| x == 0) {
-                Some(i) => i,
-                None => return Err(ReadError::InvalidName),
-            };
-            if first_null == 0 {
-                return Err(ReadError::InvalidName);
-            }
-            let name_slice = &file.name_buf[..first_null + 1];
-            let name_cstr = match CStr::from_bytes_with_nul(name_slice) {
-                Ok(cstr) => cstr,
-                Err(_) => return Err(ReadError::InvalidName),
-            };
+            let name = file.name().ok_or(ReadError::InvalidName)?;
             self.inner.seek(io::SeekFrom::Start(file.offset as u64))?;
 
             Ok(ArchiveFileAccess {
-                name: name_cstr,
+                name,
                 size: file.size,
                 offset: file.offset,
                 inner: &mut self.inner,
@@ -113,6 +190,30 @@ pub fn get_file<'a>(
             })
         })
     }
+
+    pub fn open_by_name(
+        &mut self,
+        name: &str,
+    ) -> Option<Result<ArchiveFileAccess<'_, R>, ReadError>> {
+        let index = self.find(name)?;
+        self.get_file(index)
+    }
+}
+
+impl<'a> Entry<'a> {
+    /// Fails with [`ReadError::InvalidName`] if the name is empty or not
+    /// NUL-terminated.
+    pub fn name(&self) -> Result<&'a CStr, ReadError> {
+        self.name.ok_or(ReadError::InvalidName)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size as u64
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset as u64
+    }
 }
 
 impl<'a, R: Read + Seek> ArchiveFileAccess<'a, R> {
@@ -143,3 +244,189 @@ fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         Ok(bytes_read)
     }
 }
+
+impl<'a, R: Read + Seek> Seek for ArchiveFileAccess<'a, R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let size = self.size as u64;
+        let new_pos = match pos {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(n) => size.checked_add_signed(n),
+            io::SeekFrom::Current(n) => self.reader_offset.checked_add_signed(n),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?
+        // stay within the file
+        .min(size);
+
+        self.inner
+            .seek(io::SeekFrom::Start(self.offset as u64 + new_pos))?;
+        self.reader_offset = new_pos;
+
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.reader_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::ArchiveWriter;
+
+    #[test]
+    fn invalid_name_fails_on_access() {
+        let mut writer = ArchiveWriter::new();
+        for name in [c"a.lub", c"b.lub"] {
+            std::io::Write::write_all(&mut writer.file(name).unwrap(), name.to_bytes()).unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+        // the second name without its NUL terminator
+        bytes[0x10 + 0x80..0x10 + 0x80 + 0x74].fill(b'x');
+
+        let mut archive = Archive::open(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert!(matches!(
+            archive.entry(1).unwrap().name(),
+            Err(ReadError::InvalidName)
+        ));
+        assert!(matches!(
+            archive.get_file(1).unwrap(),
+            Err(ReadError::InvalidName)
+        ));
+
+        let mut contents = Vec::new();
+        archive
+            .open_by_name("a.lub")
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"a.lub");
+    }
+
+    /// An archive with the given file table entries and `len` bytes of
+    /// data after the table.
+    fn crafted(files: &[(&str, u32, u32)], len: usize) -> Cursor<Vec<u8>> {
+        let mut bytes = b"DSARC FL".to_vec();
+        bytes.extend((files.len() as u32).to_le_bytes());
+        bytes.extend([0; 4]);
+        for &(name, size, offset) in files {
+            let mut name_buf = [0u8; 0x74];
+            name_buf[..name.len()].copy_from_slice(name.as_bytes());
+            bytes.extend(name_buf);
+            bytes.extend(size.to_le_bytes());
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend([0; 4]);
+        }
+        bytes.resize(bytes.len() + len, 0);
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn empty_archive() {
+        let archive = Archive::open(crafted(&[], 0)).unwrap();
+        assert!(archive.is_empty());
+        assert_eq!(archive.find("a.lub"), None);
+    }
+
+    #[test]
+    fn table_out_of_bounds() {
+        let mut bytes = crafted(&[("a.lub", 0, 0), ("b.lub", 0, 0)], 0).into_inner();
+        bytes.truncate(0x10 + 0x80 + 0x7f);
+        assert!(matches!(
+            Archive::open(Cursor::new(bytes)),
+            Err(ReadError::TableOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn file_out_of_bounds() {
+        // within the file table
+        assert!(matches!(
+            Archive::open(crafted(&[("a.lub", 4, 0x90), ("b.lub", 4, 0x110)], 8)),
+            Err(ReadError::FileOutOfBounds { index: 0 })
+        ));
+        // past the end of the archive
+        assert!(matches!(
+            Archive::open(crafted(&[("a.lub", 4, 0x110), ("b.lub", 4, 0x114)], 7)),
+            Err(ReadError::FileOutOfBounds { index: 1 })
+        ));
+        // empty files may point anywhere within the archive
+        let archive = Archive::open(crafted(&[("a.lub", 0, 0), ("b.lub", 4, 0x110)], 4)).unwrap();
+        assert_eq!(archive.len(), 2);
+    }
+
+    #[test]
+    fn files_overlap() {
+        assert!(matches!(
+            Archive::open(crafted(
+                &[
+                    ("a.lub", 4, 0x198),
+                    ("b.lub", 4, 0x190),
+                    ("c.lub", 9, 0x190)
+                ],
+                12
+            )),
+            Err(ReadError::FilesOverlap {
+                first: 1,
+                second: 2
+            })
+        ));
+        assert!(matches!(
+            Archive::open(crafted(&[("a.lub", 4, 0x114), ("b.lub", 4, 0x111)], 8)),
+            Err(ReadError::FilesOverlap {
+                first: 0,
+                second: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn file_seek() {
+        let mut writer = ArchiveWriter::new();
+        for (name, contents) in [(c"a.lub", &b"first"[..]), (c"b.lub", b"0123456789")] {
+            std::io::Write::write_all(&mut writer.file(name).unwrap(), contents).unwrap();
+        }
+        let mut archive = Archive::open(Cursor::new(writer.finish().unwrap())).unwrap();
+        let mut file = archive.open_by_name("b.lub").unwrap().unwrap();
+        let mut buf = [0; 3];
+
+        assert_eq!(file.seek(io::SeekFrom::Start(2)).unwrap(), 2);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"234");
+        assert_eq!(file.stream_position().unwrap(), 5);
+
+        assert_eq!(file.seek(io::SeekFrom::Current(-4)).unwrap(), 1);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"123");
+
+        assert_eq!(file.seek(io::SeekFrom::End(-3)).unwrap(), 7);
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"789");
+
+        // seeking past the end stops at the end of the file
+        assert_eq!(file.seek(io::SeekFrom::Start(100)).unwrap(), 10);
+        assert_eq!(file.seek(io::SeekFrom::End(1)).unwrap(), 10);
+        assert_eq!(file.seek(io::SeekFrom::Current(1)).unwrap(), 10);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+        // negative positions fail without moving
+        for pos in [io::SeekFrom::Current(-11), io::SeekFrom::End(-11)] {
+            let e = file.seek(pos).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(file.stream_position().unwrap(), 10);
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"012");
+    }
+}
@@ -1,24 +1,58 @@
 use std::{
-    ffi::CStr,
-    io::{self, Write},
+    ffi::{CStr, CString},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
 };
 
+const ALIGN: u64 = 0x200;
+
+/// Collects files in memory, for when their number isn't known up front,
+/// and lays them out with an [`ArchiveStreamWriter`] on
+/// [`ArchiveWriter::finish`].
 pub struct ArchiveWriter {
-    files: Vec<ArchiveFile>,
+    files: Vec<BufferedFile>,
     buf: Vec<u8>,
 }
 
+struct BufferedFile {
+    name: CString,
+    start: usize,
+    len: usize,
+}
+
+/// Writes entry data straight to `inner` and fills in the file table, which
+/// is reserved up front, on [`ArchiveStreamWriter::finish`].
+pub struct ArchiveStreamWriter<W: Write + Seek> {
+    inner: W,
+    start: u64,
+    max_files: usize,
+    files: Vec<ArchiveFile>,
+    len: u64,
+}
+
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum WriteError {
     #[error("File name is too long")]
     NameTooLong,
 
+    #[error("More files were added than were reserved")]
+    TooManyFiles,
+
+    #[error("Archive is too large for 32-bit offsets")]
+    TooLarge,
+
     #[error("IO error")]
     Io(#[from] io::Error),
 }
 
 pub struct ArchiveFileWriter<'a> {
     archive: &'a mut ArchiveWriter,
+    name: CString,
+    start: usize,
+}
+
+pub struct ArchiveStreamFileWriter<'a, W: Write + Seek> {
+    archive: &'a mut ArchiveStreamWriter<W>,
     name: [u8; 0x74],
     start: usize,
     len: usize,
@@ -30,6 +64,46 @@ pub struct ArchiveFile {
     len: usize,
 }
 
+fn file_name(name: &CStr) -> Result<[u8; 0x74], WriteError> {
+    if name.to_bytes().len() >= 0x74 {
+        return Err(WriteError::NameTooLong);
+    }
+    let mut file_name = [0; 0x74];
+    file_name[..name.to_bytes().len()].copy_from_slice(name.to_bytes());
+    Ok(file_name)
+}
+
+fn headers_size(files_count: usize) -> usize {
+    0x10 + files_count * 0x80 + 0x70
+}
+
+/// Writes the archive header and file table, with `data_start` added to
+/// each file's offset.
+fn write_headers<W: Write>(
+    mut out: W,
+    files: &[ArchiveFile],
+    files_count: usize,
+    data_start: usize,
+) -> Result<(), WriteError> {
+    out.write_all(b"DSARC FL")?;
+    let count_bytes = (files.len() as u32).to_le_bytes();
+    out.write_all(&count_bytes[..])?;
+    out.write_all(&0u32.to_le_bytes()[..])?;
+    for f in files.iter() {
+        let off = u32::try_from(f.off + data_start).map_err(|_| WriteError::TooLarge)?;
+        let len = u32::try_from(f.len).map_err(|_| WriteError::TooLarge)?;
+        out.write_all(&f.name[..])?;
+        out.write_all(&len.to_le_bytes()[..])?;
+        out.write_all(&off.to_le_bytes()[..])?;
+        out.write_all(&[0; 4])?;
+    }
+    for _ in files.len()..files_count {
+        out.write_all(&[0; 0x80])?;
+    }
+    out.write_all(&[0u8; 0x70])?;
+    Ok(())
+}
+
 impl ArchiveWriter {
     pub fn new() -> Self {
         ArchiveWriter {
@@ -46,51 +120,43 @@ pub fn with_capacity(capacity: usize) -> Self {
     }
 
     pub fn file<'a>(&'a mut self, name: &CStr) -> Result<ArchiveFileWriter<'a>, WriteError> {
-        if name.to_bytes().len() >= 0x74 {
-            return Err(WriteError::NameTooLong);
-        }
-        let mut file_name = [0; 0x74];
-        (&mut file_name[..name.to_bytes().len()]).copy_from_slice(name.to_bytes());
+        // reported here rather than on finish
+        file_name(name)?;
 
         let start = self.buf.len();
 
         Ok(ArchiveFileWriter {
             archive: self,
-            name: file_name,
-            len: 0,
+            name: name.to_owned(),
             start,
         })
     }
 
     pub fn finish(self) -> Result<Vec<u8>, WriteError> {
-        let buf_len = self.buf.len();
-        let hdrs_size = self.files.len() * 0x80 + 0x70;
-        let total_size = 0x10 + hdrs_size + buf_len;
-
-        let mut out = Vec::with_capacity(total_size);
-        out.write_all(b"DSARC FL")?;
-        let count_bytes = (self.files.len() as u32).to_le_bytes();
-        out.write_all(&count_bytes[..])?;
-        out.write_all(&0u32.to_le_bytes()[..])?;
-        for f in self.files.iter() {
-            out.write_all(&f.name[..])?;
-            let size_bytes = (f.len as u32).to_le_bytes();
-            out.write_all(&size_bytes[..])?;
-            let off_bytes = ((f.off + 0x10 + hdrs_size) as u32).to_le_bytes();
-            out.write_all(&off_bytes[..])?;
-            out.write_all(&[0; 4])?;
+        let padded_len = self.buf.len() + self.files.len() * ALIGN as usize;
+        let out = Cursor::new(Vec::with_capacity(
+            headers_size(self.files.len()) + padded_len,
+        ));
+
+        let mut writer = ArchiveStreamWriter::new(out, self.files.len())?;
+        for file in self.files.iter() {
+            writer
+                .file(&file.name)?
+                .write_all(&self.buf[file.start..file.start + file.len])?;
         }
-        out.write_all(&[0u8; 0x70])?;
-        out.write_all(&self.buf[..])?;
-        Ok(out)
+        Ok(writer.finish()?.into_inner())
+    }
+}
+
+impl Default for ArchiveWriter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl<'a> Write for ArchiveFileWriter<'a> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let written = self.archive.buf.write(buf)?;
-        self.len += written;
-        Ok(written)
+        self.archive.buf.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -101,12 +167,90 @@ fn flush(&mut self) -> io::Result<()> {
 
 impl<'a> Drop for ArchiveFileWriter<'a> {
     fn drop(&mut self) {
-        let buf_len = self.archive.buf.len();
-        let pad_len = match buf_len % 0x200 {
-            0 => buf_len,
-            r => buf_len + (0x200 - r),
-        };
-        self.archive.buf.resize(pad_len, 0);
+        self.archive.files.push(BufferedFile {
+            name: std::mem::take(&mut self.name),
+            start: self.start,
+            len: self.archive.buf.len() - self.start,
+        });
+    }
+}
+
+impl<W: Write + Seek> ArchiveStreamWriter<W> {
+    /// Starts an archive at the current position of `inner`, reserving
+    /// table space for up to `max_files` files.
+    pub fn new(mut inner: W, max_files: usize) -> Result<Self, WriteError> {
+        let start = inner.stream_position()?;
+        write_headers(&mut inner, &[], max_files, 0)?;
+
+        Ok(ArchiveStreamWriter {
+            inner,
+            start,
+            max_files,
+            files: Vec::new(),
+            len: 0,
+        })
+    }
+
+    fn pad(&mut self) -> io::Result<()> {
+        let r = self.len % ALIGN;
+        if r != 0 {
+            io::copy(&mut io::repeat(0).take(ALIGN - r), &mut self.inner)?;
+            self.len += ALIGN - r;
+        }
+        Ok(())
+    }
+
+    pub fn file<'a>(
+        &'a mut self,
+        name: &CStr,
+    ) -> Result<ArchiveStreamFileWriter<'a, W>, WriteError> {
+        if self.files.len() >= self.max_files {
+            return Err(WriteError::TooManyFiles);
+        }
+        let file_name = file_name(name)?;
+        self.pad()?;
+
+        let start = self.len as usize;
+
+        Ok(ArchiveStreamFileWriter {
+            archive: self,
+            name: file_name,
+            len: 0,
+            start,
+        })
+    }
+
+    /// Pads the last file, writes the file table and returns `inner`
+    /// positioned at the end of the archive.
+    pub fn finish(mut self) -> Result<W, WriteError> {
+        self.pad()?;
+        let end = self.inner.stream_position()?;
+
+        self.inner.seek(SeekFrom::Start(self.start))?;
+        let hdrs_size = headers_size(self.max_files);
+        write_headers(&mut self.inner, &self.files, self.max_files, hdrs_size)?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<'a, W: Write + Seek> Write for ArchiveStreamFileWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.archive.inner.write(buf)?;
+        self.len += written;
+        self.archive.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.archive.inner.flush()
+    }
+}
+
+impl<'a, W: Write + Seek> Drop for ArchiveStreamFileWriter<'a, W> {
+    fn drop(&mut self) {
         self.archive.files.push(ArchiveFile {
             name: self.name,
             off: self.start,
@@ -1,8 +1,16 @@
-use std::{ffi::OsString, fs::File, io::Write, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::Parser;
 use makaikit_fafullfs::Archive;
+use makaikit_ykcmp::MaybeCompressed;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -10,6 +18,102 @@ struct Args {
     out_dir: Option<PathBuf>,
     #[arg(short = 'V', long)]
     verbose: bool,
+    /// Decompress YKCMP entries while extracting them
+    #[arg(short, long)]
+    decompress: bool,
+    /// Extract every entry, even those the manifest says are unchanged
+    #[arg(short, long)]
+    force: bool,
+}
+
+/// Name of the manifest written to the output directory, which records what
+/// was extracted so later runs only write what changed.
+const MANIFEST_NAME: &str = ".fafullfs-manifest.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    decompress: bool,
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    checksum: u64,
+    size: u64,
+    timestamp: u64,
+}
+
+fn read_manifest(path: &Path) -> anyhow::Result<Manifest> {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Unable to parse manifest {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+        Err(e) => Err(e).with_context(|| format!("Unable to open manifest {}", path.display())),
+    }
+}
+
+fn write_manifest(path: &Path, manifest: &Manifest) -> anyhow::Result<()> {
+    // write to the side so that an interrupted run leaves the old manifest
+    let tmp_path = path.with_extension("json.tmp");
+    let mut out = BufWriter::new(
+        File::create(&tmp_path)
+            .with_context(|| format!("Unable to create manifest {}", tmp_path.display()))?,
+    );
+    serde_json::to_writer_pretty(&mut out, manifest).context("Unable to write manifest")?;
+    out.flush().context("Unable to write manifest")?;
+    drop(out);
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Unable to replace manifest {}", path.display()))
+}
+
+/// Where `path`, from the archive or the manifest, goes under `dir`.
+fn entry_out_path(dir: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let relative = PathBuf::from(path.replace('\\', "/"));
+    anyhow::ensure!(
+        relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))),
+        "Refusing to write {} outside of {}",
+        path,
+        dir.display()
+    );
+    Ok(dir.join(relative))
+}
+
+fn extract_entry<B: AsRef<[u8]>>(
+    archive: &Archive<io::Cursor<B>>,
+    index: usize,
+    entry_out_path: &Path,
+    decompress: bool,
+) -> anyhow::Result<()> {
+    let entry_bytes = archive
+        .file_bytes(index)
+        .unwrap()
+        .with_context(|| format!("Unable to read file entry {}", index))?;
+    let mut entry_out_parent_dir = entry_out_path.to_path_buf();
+    entry_out_parent_dir.pop();
+    std::fs::create_dir_all(&entry_out_parent_dir).with_context(|| {
+        format!(
+            "Unable to create directories through path {}",
+            entry_out_parent_dir.display()
+        )
+    })?;
+    let mut out_file = File::create(entry_out_path).with_context(|| {
+        format!(
+            "Unable to open file {} for write to write entry {}",
+            entry_out_path.display(),
+            index
+        )
+    })?;
+    eprintln!("writing {}", entry_out_path.display());
+    if decompress {
+        let mut reader = MaybeCompressed::new(entry_bytes)
+            .with_context(|| format!("Unable to decompress {}", entry_out_path.display()))?;
+        io::copy(&mut reader, &mut out_file).map(drop)
+    } else {
+        out_file.write_all(entry_bytes)
+    }
+    .context("Unable to write archive file bytes to destination")
 }
 
 fn main() -> anyhow::Result<()> {
@@ -29,38 +133,95 @@ fn main() -> anyhow::Result<()> {
 
     let file = File::open(&args.path).context("unable to read input path")?;
 
-    let mut archive = Archive::open(file).context("unable to open archive at input path")?;
-    let archive_file_count = archive.len();
-    for i in 0..archive_file_count {
-        let mut accessor = archive
-            .get_file(i)
-            .unwrap()
-            .with_context(|| format!("Unable to read file entry {}", i))?;
-        let entry_path_osstring = accessor
+    // SAFETY: the archive is only read, and is expected not to change while
+    // it is being unpacked
+    let archive = unsafe { Archive::map(&file) }.context("unable to open archive at input path")?;
+
+    let manifest_path = out_dir_path.join(MANIFEST_NAME);
+    let old_manifest = read_manifest(&manifest_path)?;
+    // files extracted with the other decompression setting can't be reused
+    let reuse = !args.force && old_manifest.decompress == args.decompress;
+
+    let mut manifest = Manifest {
+        decompress: args.decompress,
+        entries: BTreeMap::new(),
+    };
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    // unchanged entries that are extracted again anyway
+    let mut refreshed = 0;
+    let mut to_extract = Vec::new();
+    let mut out_paths = HashSet::new();
+    for (i, entry) in archive.entries().enumerate() {
+        let path = entry
             .path()
-            .to_str()
-            .context("Unable to parse file path")?;
-        let entry_path = PathBuf::from(entry_path_osstring);
-        let entry_out_path = out_dir_path.join(entry_path);
-        let mut entry_out_parent_dir = entry_out_path.clone();
-        entry_out_parent_dir.pop();
-        std::fs::create_dir_all(&entry_out_parent_dir).with_context(|| {
-            format!(
-                "Unable to create directories through path {}",
-                entry_out_parent_dir.display()
-            )
-        })?;
-        let mut out_file = File::create(&entry_out_path).with_context(|| {
-            format!(
-                "Unable to open file {} for write to write {}",
-                entry_out_path.display(),
-                entry_path_osstring
-            )
-        })?;
-        eprintln!("writing {}", entry_out_path.display());
-        std::io::copy(&mut accessor, &mut out_file)
-            .context("Unable to write archive file bytes to destination")?;
+            .ok()
+            .and_then(|path| path.to_str().ok())
+            .with_context(|| format!("Unable to parse file path of entry {}", i))?;
+        let out_path = entry_out_path(&out_dir_path, path)?;
+        // only the first entry with a path is extracted, the one find gives.
+        // paths differing in case are the same file on some file systems
+        if !out_paths.insert(out_path.to_string_lossy().to_lowercase()) {
+            eprintln!("skipping entry {} with duplicate path {}", i, path);
+            continue;
+        }
+        let manifest_entry = ManifestEntry {
+            checksum: entry.checksum(),
+            size: entry.size(),
+            timestamp: entry.timestamp(),
+        };
+        manifest.entries.insert(path.to_owned(), manifest_entry);
+
+        match old_manifest.entries.get(path) {
+            None => added.push(path),
+            Some(old) if *old != manifest_entry => changed.push(path),
+            Some(_) if !reuse || !out_path.is_file() => refreshed += 1,
+            Some(_) => continue,
+        }
+        to_extract.push((i, out_path));
+    }
+
+    to_extract
+        .into_par_iter()
+        .try_for_each(|(i, out_path)| extract_entry(&archive, i, &out_path, args.decompress))?;
+
+    let mut removed = Vec::new();
+    for path in old_manifest.entries.keys() {
+        if manifest.entries.contains_key(path) {
+            continue;
+        }
+        let out_path = entry_out_path(&out_dir_path, path)?;
+        // spelled differently, but extracted to the same file
+        if out_paths.contains(&out_path.to_string_lossy().to_lowercase()) {
+            continue;
+        }
+        match std::fs::remove_file(&out_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Unable to remove {}", out_path.display()));
+            }
+            _ => removed.push(path),
+        }
+    }
+
+    write_manifest(&manifest_path, &manifest)?;
+
+    for path in added.iter() {
+        println!("added {}", path);
+    }
+    for path in changed.iter() {
+        println!("changed {}", path);
+    }
+    for path in removed.iter() {
+        println!("removed {}", path);
     }
+    println!(
+        "{} added, {} changed, {} removed, {} extracted again, {} unchanged",
+        added.len(),
+        changed.len(),
+        removed.len(),
+        refreshed,
+        manifest.entries.len() - added.len() - changed.len() - refreshed,
+    );
 
     Ok(())
 }
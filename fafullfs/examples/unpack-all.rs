@@ -0,0 +1,393 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Cursor, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use makaikit_nlsd::NlsdRead;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Unpacks an archive, then every archive, compressed file and sound inside
+/// it, into a directory tree.
+#[derive(Parser, Debug)]
+struct Args {
+    path: PathBuf,
+    out_dir: PathBuf,
+    #[arg(short = 'V', long)]
+    verbose: bool,
+}
+
+/// Name of the manifest written to the output directory, which records how
+/// each unpacked file was nested so it can be packed again.
+const MANIFEST_NAME: &str = ".unpack-manifest.json";
+
+const NLSD_HEADER_LEN: usize = 0x18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Fafullfs,
+    DsarcFl,
+    Ykcmp,
+    Nlsd,
+}
+
+impl Format {
+    fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes.get(..8)? {
+            b"FAFULLFS" => return Some(Format::Fafullfs),
+            b"DSARC FL" => return Some(Format::DsarcFl),
+            b"YKCMP_V1" => return Some(Format::Ykcmp),
+            _ => {}
+        }
+
+        // NLSD has no magic, so the format and size fields have to agree
+        if bytes.len() < NLSD_HEADER_LEN {
+            return None;
+        }
+        let format = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let total_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if (format == 5 || format == 7) && NLSD_HEADER_LEN + total_size as usize == bytes.len() {
+            return Some(Format::Nlsd);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// File name of the unpacked archive
+    source: String,
+    root: Node,
+}
+
+/// How a file was unpacked. Containers are unpacked into a directory at the
+/// path the file itself would have had, and their entries' paths are
+/// relative to it. An entry whose path another entry already took, ignoring
+/// case, is unpacked to the path in its `file` instead.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Node {
+    /// Written out as is
+    Raw,
+    Fafullfs {
+        unk: u32,
+        entries: Vec<FafullfsEntry>,
+    },
+    DsarcFl {
+        entries: Vec<DsarcFlEntry>,
+    },
+    /// Decompressed in place. Only the encoding is kept, so compressing the
+    /// contents again gives a valid file but not the original bytes.
+    Ykcmp {
+        encoding: u32,
+        contents: Box<Node>,
+    },
+    /// Split into its sections, in the encoding given by `format`
+    Nlsd {
+        format: u32,
+        sample_rate: u16,
+        stereo: bool,
+        samples: u32,
+        begin: Option<String>,
+        middle: String,
+        end: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FafullfsEntry {
+    /// The path as stored in the archive, which may use `\` separators
+    path: String,
+    checksum: u64,
+    timestamp: u64,
+    unk: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(flatten)]
+    node: Node,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DsarcFlEntry {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(flatten)]
+    node: Node,
+}
+
+/// Joins an entry path onto `dir`, refusing paths that could leave it.
+fn entry_out_path(dir: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let relative = PathBuf::from(path.replace('\\', "/"));
+    anyhow::ensure!(
+        relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))),
+        "Refusing to unpack {} outside of {}",
+        path,
+        dir.display()
+    );
+    Ok(dir.join(relative))
+}
+
+/// Picks where each of `paths` is unpacked to, giving `None` for those that
+/// keep their path. Paths that would land on a file already taken, as
+/// duplicates or by differing only in case, get the entry's index added
+/// before their extension.
+fn unique_paths<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<Option<String>> {
+    let key = |path: &str| path.replace('\\', "/").to_lowercase();
+    let paths: Vec<&str> = paths.into_iter().collect();
+    let mut taken = HashSet::with_capacity(paths.len());
+    let mut files = Vec::with_capacity(paths.len());
+    for (index, path) in paths.into_iter().enumerate() {
+        if taken.insert(key(path)) {
+            files.push(None);
+            continue;
+        }
+        let name_start = path.rfind(['/', '\\']).map_or(0, |i| i + 1);
+        let ext_start = match path[name_start..].rfind('.') {
+            Some(0) | None => path.len(),
+            Some(i) => name_start + i,
+        };
+        let (stem, ext) = path.split_at(ext_start);
+        let mut suffix = index;
+        let file = loop {
+            let file = format!("{}~{}{}", stem, suffix, ext);
+            if taken.insert(key(&file)) {
+                break file;
+            }
+            suffix += 1;
+        };
+        files.push(Some(file));
+    }
+    files
+}
+
+/// Unpacks `bytes` to `out_path`. A nested file that looks like a container
+/// but can't be read as one is written as is.
+fn unpack(bytes: &[u8], out_path: &Path, verbose: bool) -> anyhow::Result<Node> {
+    let node = match Format::detect(bytes) {
+        None => None,
+        Some(Format::Fafullfs) => Some(unpack_fafullfs(bytes, out_path, verbose)?),
+        Some(Format::DsarcFl) => Some(unpack_dsarcfl(bytes, out_path, verbose)?),
+        Some(Format::Ykcmp) => Some(unpack_ykcmp(bytes, out_path, verbose)?),
+        Some(Format::Nlsd) => Some(unpack_nlsd(bytes, out_path, verbose)?),
+    };
+    match node {
+        None => {}
+        Some(Ok(node)) => return Ok(node),
+        Some(Err(e)) => eprintln!(
+            "{} is malformed, writing it as is: {:#}",
+            out_path.display(),
+            e
+        ),
+    }
+
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!(
+                "Unable to create directories through path {}",
+                parent.display()
+            )
+        })?;
+    }
+    if verbose {
+        eprintln!("writing {}", out_path.display());
+    }
+    std::fs::write(out_path, bytes)
+        .with_context(|| format!("Unable to write {}", out_path.display()))?;
+    Ok(Node::Raw)
+}
+
+/// The result of unpacking a container: its node, or why it couldn't be read
+/// as the container it looks like, in which case nothing has been written.
+type Unpacked = Result<Node, anyhow::Error>;
+
+fn unpack_ykcmp(bytes: &[u8], out_path: &Path, verbose: bool) -> anyhow::Result<Unpacked> {
+    let decompressed = match makaikit_ykcmp::decompress_to_vec(bytes) {
+        Ok(decompressed) => decompressed,
+        Err(e) => return Ok(Err(e.into())),
+    };
+    // the header was validated by decompressing
+    let encoding = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    Ok(Ok(Node::Ykcmp {
+        encoding,
+        contents: Box::new(unpack(&decompressed, out_path, verbose)?),
+    }))
+}
+
+fn unpack_fafullfs(bytes: &[u8], out_dir: &Path, verbose: bool) -> anyhow::Result<Unpacked> {
+    let archive = match makaikit_fafullfs::Archive::from_bytes(bytes) {
+        Ok(archive) => archive,
+        Err(e) => return Ok(Err(e.into())),
+    };
+    // check every entry before writing anything, so that a malformed
+    // archive can still be written as is
+    let mut files = Vec::with_capacity(archive.len());
+    for (i, entry) in archive.entries().enumerate() {
+        let Some(path) = entry.path().ok().and_then(|path| path.to_str().ok()) else {
+            return Ok(Err(anyhow::anyhow!(
+                "Unable to parse file path of entry {}",
+                i
+            )));
+        };
+        let entry_bytes = match archive.file_bytes(i).unwrap() {
+            Ok(entry_bytes) => entry_bytes,
+            Err(e) => return Ok(Err(e.into())),
+        };
+        files.push((entry, path, entry_bytes));
+    }
+    let unique = unique_paths(files.iter().map(|&(_, path, _)| path));
+    let files = files
+        .into_iter()
+        .zip(unique)
+        .map(|((entry, path, entry_bytes), file)| {
+            let out_path = entry_out_path(out_dir, file.as_deref().unwrap_or(path))?;
+            Ok((entry, path, entry_bytes, file, out_path))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let entries = files
+        .into_par_iter()
+        .map(|(entry, path, entry_bytes, file, out_path)| {
+            Ok(FafullfsEntry {
+                path: path.to_owned(),
+                checksum: entry.checksum(),
+                timestamp: entry.timestamp(),
+                unk: entry.unk(),
+                file,
+                node: unpack(entry_bytes, &out_path, verbose)?,
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(Ok(Node::Fafullfs {
+        unk: archive.unk(),
+        entries,
+    }))
+}
+
+fn unpack_dsarcfl(bytes: &[u8], out_dir: &Path, verbose: bool) -> anyhow::Result<Unpacked> {
+    let archive = match makaikit_dsarcfl::Archive::open(Cursor::new(bytes)) {
+        Ok(archive) => archive,
+        Err(e) => return Ok(Err(e.into())),
+    };
+    let mut names = Vec::with_capacity(archive.len());
+    for (i, entry) in archive.entries().enumerate() {
+        let Some(name) = entry.name().ok().and_then(|name| name.to_str().ok()) else {
+            return Ok(Err(anyhow::anyhow!(
+                "Unable to parse file name of entry {}",
+                i
+            )));
+        };
+        names.push(name.to_owned());
+    }
+
+    let unique = unique_paths(names.iter().map(String::as_str));
+    let mut entries = Vec::with_capacity(archive.len());
+    for ((entry, name), file) in archive.entries().zip(names).zip(unique) {
+        // entries are checked to lie within the archive when it is opened
+        let start = entry.offset() as usize;
+        let entry_bytes = &bytes[start..start + entry.size() as usize];
+        let out_path = entry_out_path(out_dir, file.as_deref().unwrap_or(&name))?;
+        let node = unpack(entry_bytes, &out_path, verbose)?;
+        entries.push(DsarcFlEntry { name, file, node });
+    }
+
+    Ok(Ok(Node::DsarcFl { entries }))
+}
+
+fn unpack_nlsd(bytes: &[u8], out_dir: &Path, verbose: bool) -> anyhow::Result<Unpacked> {
+    let mut nlsd = match NlsdRead::open(Cursor::new(bytes)) {
+        Ok(nlsd) => nlsd,
+        Err(e) => return Ok(Err(e.into())),
+    };
+    let extension = match nlsd.format() {
+        5 => "wav",
+        7 => "ogg",
+        _ => unreachable!(),
+    };
+    std::fs::create_dir_all(out_dir).with_context(|| {
+        format!(
+            "Unable to create directories through path {}",
+            out_dir.display()
+        )
+    })?;
+    let write_section = |section: &mut dyn std::io::Read, index: u32| {
+        let name = format!("{}.{}", index, extension);
+        let section_path = out_dir.join(&name);
+        if verbose {
+            eprintln!("writing {}", section_path.display());
+        }
+        let mut out = BufWriter::new(
+            File::create(&section_path)
+                .with_context(|| format!("Unable to create {}", section_path.display()))?,
+        );
+        std::io::copy(section, &mut out)
+            .and_then(|_| out.flush())
+            .with_context(|| format!("Unable to write {}", section_path.display()))?;
+        anyhow::Ok(name)
+    };
+
+    let begin = match nlsd.section_begin()? {
+        Some(mut section) => Some(write_section(&mut section, 0)?),
+        None => None,
+    };
+    let middle = write_section(&mut nlsd.section_middle()?, 1)?;
+    let end = match nlsd.section_end()? {
+        Some(mut section) => Some(write_section(&mut section, 2)?),
+        None => None,
+    };
+
+    Ok(Ok(Node::Nlsd {
+        format: nlsd.format(),
+        sample_rate: nlsd.sample_rate(),
+        stereo: nlsd.stereo(),
+        samples: nlsd.samples(),
+        begin,
+        middle,
+        end,
+    }))
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let file = File::open(&args.path)
+        .with_context(|| format!("Could not open input file {}", args.path.display()))?;
+    // SAFETY: the file is only read, and is expected not to change while it
+    // is being unpacked
+    let bytes = unsafe { memmap2::Mmap::map(&file) }.context("unable to map input file")?;
+    let root = match Format::detect(&bytes) {
+        Some(Format::Fafullfs) => unpack_fafullfs(&bytes, &args.out_dir, args.verbose)?,
+        Some(Format::DsarcFl) => unpack_dsarcfl(&bytes, &args.out_dir, args.verbose)?,
+        _ => anyhow::bail!(
+            "{} is not a FAFULLFS or DSARC FL archive",
+            args.path.display()
+        ),
+    }
+    .with_context(|| format!("Unable to open {}", args.path.display()))?;
+    let manifest = Manifest {
+        source: args
+            .path
+            .file_name()
+            .context("Input path has no file name")?
+            .to_string_lossy()
+            .into_owned(),
+        root,
+    };
+
+    let manifest_path = args.out_dir.join(MANIFEST_NAME);
+    let mut out = BufWriter::new(
+        File::create(&manifest_path)
+            .with_context(|| format!("Unable to create manifest {}", manifest_path.display()))?,
+    );
+    serde_json::to_writer_pretty(&mut out, &manifest).context("Unable to write manifest")?;
+    out.flush().context("Unable to write manifest")?;
+
+    Ok(())
+}
@@ -0,0 +1,511 @@
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs::File,
+    io::{self, Cursor, Read, Seek},
+};
+
+pub struct Archive<R: Read + Seek> {
+    inner: R,
+    paths: Vec<u8>,
+    files: Vec<ArchiveFile>,
+    /// Normalised path of each entry, if it is valid UTF-8
+    normalized: Vec<Option<Box<str>>>,
+    /// Normalised path to the index of its first entry
+    index: HashMap<Box<str>, usize>,
+    unk: u32,
+}
+
+struct ArchiveFile {
+    path_off: u64,
+    checksum: u64,
+    unk: u64,
+    size: u64,
+    offset: u64,
+    timestamp: u64,
+}
+
+/// Metadata of a file in an [`Archive`].
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    path: Option<&'a CStr>,
+    checksum: u64,
+    unk: u64,
+    size: u64,
+    offset: u64,
+    timestamp: u64,
+}
+
+pub struct ArchiveFileAccess<'a, R: Read + Seek> {
+    path: &'a CStr,
+    checksum: u64,
+    unk: u64,
+    offset: u64,
+    size: u64,
+    timestamp: u64,
+
+    inner: &'a mut R,
+    reader_offset: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Invalid magic identifier (FAFULLFS)")]
+    InvalidMagic,
+
+    #[error("Something is too large to fit in memory")]
+    TooLarge,
+
+    #[error("The name of a file in this archive is invalid")]
+    InvalidName,
+
+    #[error("File {index} extends past the end of the archive")]
+    FileOutOfBounds { index: usize },
+
+    #[error("Invalid glob pattern")]
+    InvalidPattern(#[from] glob::PatternError),
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+fn path_at(paths: &[u8], path_off: u64) -> Result<&CStr, Error> {
+    let path_slice = paths.get(path_off as usize..).ok_or(Error::InvalidName)?;
+    match CStr::from_bytes_until_nul(path_slice) {
+        Ok(cstr) if !cstr.is_empty() => Ok(cstr),
+        _ => Err(Error::InvalidName),
+    }
+}
+
+/// Lowercases ASCII letters, turns backslashes into slashes and drops
+/// leading and repeated slashes, the way the game resolves paths.
+fn normalize_path(path: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(path.len());
+    for &b in path {
+        let b = match b {
+            b'\\' => b'/',
+            b => b.to_ascii_lowercase(),
+        };
+        if b == b'/' && out.last().is_none_or(|&last| last == b'/') {
+            continue;
+        }
+        out.push(b);
+    }
+    out
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn open(mut inner: R) -> Result<Self, Error> {
+        let mut header_buf = [0u8; 40];
+        inner.read_exact(&mut header_buf[..])?;
+
+        if &header_buf[0..8] != b"FAFULLFS" {
+            return Err(Error::InvalidMagic);
+        }
+
+        let files_count =
+            u32::from_le_bytes(unsafe { *(&header_buf[8..12] as *const [u8] as *const [u8; 4]) });
+        let unk =
+            u32::from_le_bytes(unsafe { *(&header_buf[12..16] as *const [u8] as *const [u8; 4]) });
+        let paths_off =
+            u64::from_le_bytes(unsafe { *(&header_buf[16..24] as *const [u8] as *const [u8; 8]) });
+        let paths_len =
+            u64::from_le_bytes(unsafe { *(&header_buf[24..32] as *const [u8] as *const [u8; 8]) });
+        let info_off =
+            u64::from_le_bytes(unsafe { *(&header_buf[32..40] as *const [u8] as *const [u8; 8]) });
+
+        if paths_len > usize::MAX as u64 || files_count > usize::MAX as u32 {
+            return Err(Error::TooLarge);
+        }
+
+        let mut paths_vec = Vec::with_capacity(paths_len as usize);
+        paths_vec.resize(paths_len as usize, 0);
+        inner.seek(io::SeekFrom::Start(paths_off))?;
+        inner.read_exact(&mut paths_vec[..paths_len as usize])?;
+
+        let mut files = Vec::with_capacity(files_count as usize);
+        for i in 0..files_count {
+            let mut file_header_buf = [0u8; 48];
+
+            inner.seek(io::SeekFrom::Start(info_off + (i as u64) * 48))?;
+            inner.read_exact(&mut file_header_buf[..48])?;
+
+            let checksum = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[0..8] as *const [u8] as *const [u8; 8])
+            });
+            let path_off = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[8..16] as *const [u8] as *const [u8; 8])
+            });
+            let unk = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[16..24] as *const [u8] as *const [u8; 8])
+            });
+            let size = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[24..32] as *const [u8] as *const [u8; 8])
+            });
+            let offset = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[32..40] as *const [u8] as *const [u8; 8])
+            });
+            let timestamp = u64::from_le_bytes(unsafe {
+                *(&file_header_buf[40..48] as *const [u8] as *const [u8; 8])
+            });
+
+            files.push(ArchiveFile {
+                checksum,
+                path_off,
+                unk,
+                size,
+                offset,
+                timestamp,
+            });
+        }
+
+        // entries with invalid paths can't be found, only reported when
+        // they are accessed
+        let normalized: Vec<Option<Box<str>>> = files
+            .iter()
+            .map(|file| {
+                let path = path_at(&paths_vec, file.path_off).ok()?;
+                String::from_utf8(normalize_path(path.to_bytes()))
+                    .ok()
+                    .map(String::into_boxed_str)
+            })
+            .collect();
+        let mut index = HashMap::with_capacity(files.len());
+        for (i, path) in normalized.iter().enumerate() {
+            if let Some(path) = path {
+                index.entry(path.clone()).or_insert(i);
+            }
+        }
+
+        Ok(Archive {
+            inner,
+            paths: paths_vec,
+            files,
+            normalized,
+            index,
+            unk,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// The unidentified header field after the file count.
+    pub fn unk(&self) -> u32 {
+        self.unk
+    }
+
+    pub fn get_file<'a>(
+        &'a mut self,
+        index: usize,
+    ) -> Option<Result<ArchiveFileAccess<'a, R>, Error>> {
+        self.files.get(index).map(|file| {
+            let path_cstr = path_at(&self.paths, file.path_off)?;
+
+            self.inner.seek(io::SeekFrom::Start(file.offset))?;
+
+            Ok(ArchiveFileAccess {
+                path: path_cstr,
+                checksum: file.checksum,
+                size: file.size,
+                offset: file.offset,
+                timestamp: file.timestamp,
+                unk: file.unk,
+                inner: &mut self.inner,
+                reader_offset: 0,
+            })
+        })
+    }
+
+    pub fn path(&self, index: usize) -> Option<Result<&CStr, Error>> {
+        self.entry(index).map(|entry| entry.path())
+    }
+
+    fn file_entry<'a>(&'a self, file: &ArchiveFile) -> Entry<'a> {
+        Entry {
+            path: path_at(&self.paths, file.path_off).ok(),
+            checksum: file.checksum,
+            unk: file.unk,
+            size: file.size,
+            offset: file.offset,
+            timestamp: file.timestamp,
+        }
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = Entry<'_>> {
+        self.files.iter().map(|file| self.file_entry(file))
+    }
+
+    pub fn entry(&self, index: usize) -> Option<Entry<'_>> {
+        self.files.get(index).map(|file| self.file_entry(file))
+    }
+
+    /// Finds the first entry with the given path, ignoring case and
+    /// treating backslashes as slashes.
+    pub fn find(&self, path: &str) -> Option<usize> {
+        let path = String::from_utf8(normalize_path(path.as_bytes())).ok()?;
+        self.index.get(path.as_str()).copied()
+    }
+
+    /// Returns the indices of the entries matching `pattern`, in archive
+    /// order. Paths are normalised as in [`Archive::find`], `*` and `?`
+    /// stay within one directory and `**` spans any number of them.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<usize>, Error> {
+        let pattern = String::from_utf8_lossy(&normalize_path(pattern.as_bytes())).into_owned();
+        let pattern = glob::Pattern::new(&pattern)?;
+        let options = glob::MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+
+        Ok(self
+            .normalized
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| {
+                path.as_ref()
+                    .is_some_and(|path| pattern.matches_with(path, options))
+            })
+            .map(|(i, _)| i)
+            .collect())
+    }
+}
+
+impl<B: AsRef<[u8]>> Archive<Cursor<B>> {
+    /// Opens an archive that is already in memory.
+    pub fn from_bytes(data: B) -> Result<Self, Error> {
+        Self::open(Cursor::new(data))
+    }
+
+    /// Returns the contents of the entry at `index` without copying them.
+    pub fn file_bytes(&self, index: usize) -> Option<Result<&[u8], Error>> {
+        let file = self.files.get(index)?;
+        let data = self.inner.get_ref().as_ref();
+        Some(
+            file.offset
+                .checked_add(file.size)
+                .and_then(|end| data.get(file.offset as usize..end as usize))
+                .ok_or(Error::FileOutOfBounds { index }),
+        )
+    }
+}
+
+impl Archive<Cursor<memmap2::Mmap>> {
+    /// Memory-maps `file` and opens it as an archive.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the archive is
+    /// alive, see [`memmap2::Mmap::map`].
+    pub unsafe fn map(file: &File) -> Result<Self, Error> {
+        Self::from_bytes(memmap2::Mmap::map(file)?)
+    }
+}
+
+impl<'a> Entry<'a> {
+    /// Fails with [`Error::InvalidName`] if the path is empty or not
+    /// NUL-terminated.
+    pub fn path(&self) -> Result<&'a CStr, Error> {
+        self.path.ok_or(Error::InvalidName)
+    }
+
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The raw timestamp field. Its format is unknown.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The unidentified field after the path offset.
+    pub fn unk(&self) -> u64 {
+        self.unk
+    }
+}
+
+impl<'a, R: Read + Seek> ArchiveFileAccess<'a, R> {
+    pub fn path(&self) -> &CStr {
+        self.path
+    }
+
+    /// The raw timestamp field. Its format is unknown.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The unidentified field after the path offset.
+    pub fn unk(&self) -> u64 {
+        self.unk
+    }
+}
+
+impl<'a, R: Read + Seek> Read for ArchiveFileAccess<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let buf_len = buf.len() as u64;
+        if buf_len == 0 {
+            return Ok(0);
+        }
+        let read_dest = self.offset + self.reader_offset + buf_len;
+        let max_read_dest = self.offset + self.size;
+        let slice_end = max_read_dest.min(read_dest) - self.offset - self.reader_offset;
+        if slice_end == 0 {
+            return Ok(0);
+        }
+        let bytes_read = self.inner.read(&mut buf[..slice_end as usize])?;
+        self.reader_offset += bytes_read as u64;
+
+        Ok(bytes_read)
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for ArchiveFileAccess<'a, R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let size = self.size;
+        let new_pos = match pos {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(n) => size.checked_add_signed(n),
+            io::SeekFrom::Current(n) => self.reader_offset.checked_add_signed(n),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?
+        // stay within the file
+        .min(size);
+
+        self.inner
+            .seek(io::SeekFrom::Start(self.offset + new_pos))?;
+        self.reader_offset = new_pos;
+
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.reader_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::ArchiveWriter;
+
+    fn archive(paths: &[&CStr]) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for path in paths {
+            writer
+                .file(path)
+                .unwrap()
+                .write_all(path.to_bytes())
+                .unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn find_and_glob() {
+        let bytes = archive(&[c"Data\\Script\\a.lub", c"data/script/b.lub", c"data/c.dat"]);
+        let archive = Archive::from_bytes(bytes).unwrap();
+        assert_eq!(archive.find("DATA/script/A.LUB"), Some(0));
+        assert_eq!(archive.find("/data//script\\b.lub"), Some(1));
+        assert_eq!(archive.find("data/b.lub"), None);
+        assert_eq!(archive.glob("data/*.dat").unwrap(), vec![2]);
+        assert_eq!(archive.glob("Data/**/*.lub").unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn invalid_path_fails_on_access() {
+        let mut bytes = archive(&[c"a.dat", c"b.dat"]);
+        // the last path without its NUL terminator
+        let paths_off = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+        let paths_len = u64::from_le_bytes(bytes[24..32].try_into().unwrap()) as usize;
+        bytes[paths_off + paths_len - 1] = b'x';
+
+        let mut archive = Archive::from_bytes(bytes).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert!(matches!(
+            archive.entry(1).unwrap().path(),
+            Err(Error::InvalidName)
+        ));
+        assert!(matches!(
+            archive.get_file(1).unwrap(),
+            Err(Error::InvalidName)
+        ));
+        assert_eq!(archive.find("b.dat"), None);
+        assert_eq!(archive.glob("*.dat").unwrap(), vec![0]);
+
+        let mut contents = Vec::new();
+        archive
+            .get_file(archive.find("a.dat").unwrap())
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"a.dat");
+    }
+
+    #[test]
+    fn file_seek() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (path, contents) in [(c"a.dat", &b"first"[..]), (c"b.dat", b"0123456789")] {
+            writer.file(path).unwrap().write_all(contents).unwrap();
+        }
+        let mut archive = Archive::from_bytes(writer.finish().unwrap().into_inner()).unwrap();
+        let mut file = archive.get_file(1).unwrap().unwrap();
+        let mut buf = [0; 3];
+
+        assert_eq!(file.seek(io::SeekFrom::Start(2)).unwrap(), 2);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"234");
+        assert_eq!(file.stream_position().unwrap(), 5);
+
+        assert_eq!(file.seek(io::SeekFrom::Current(-4)).unwrap(), 1);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"123");
+
+        assert_eq!(file.seek(io::SeekFrom::End(-3)).unwrap(), 7);
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"789");
+
+        // seeking past the end stops at the end of the file
+        assert_eq!(file.seek(io::SeekFrom::Start(100)).unwrap(), 10);
+        assert_eq!(file.seek(io::SeekFrom::End(1)).unwrap(), 10);
+        assert_eq!(file.seek(io::SeekFrom::Current(1)).unwrap(), 10);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+        // negative positions fail without moving
+        for pos in [io::SeekFrom::Current(-11), io::SeekFrom::End(-11)] {
+            let e = file.seek(pos).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(file.stream_position().unwrap(), 10);
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"012");
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    ffi::CStr,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+const HEADER_LEN: u64 = 40;
+const ALIGN: u64 = 0x10;
+
+/// Writes entry data straight to `inner`, followed by the path table and
+/// file records on [`ArchiveWriter::finish`], which then fills in the
+/// header.
+pub struct ArchiveWriter<W: Write + Seek> {
+    inner: W,
+    start: u64,
+    len: u64,
+    paths: Vec<u8>,
+    files: Vec<ArchiveFile>,
+    unk: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum WriteError {
+    #[error("File path is empty")]
+    EmptyPath,
+
+    #[error("Too many files for a FAFULLFS archive")]
+    TooManyFiles,
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+pub struct ArchiveFileWriter<'a, W: Write + Seek> {
+    archive: &'a mut ArchiveWriter<W>,
+    path_off: u64,
+    start: u64,
+    len: u64,
+    unk: u64,
+    timestamp: u64,
+    checksum: u64,
+}
+
+struct ArchiveFile {
+    checksum: u64,
+    path_off: u64,
+    unk: u64,
+    size: u64,
+    offset: u64,
+    timestamp: u64,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    /// Starts an archive at the current position of `inner`.
+    pub fn new(mut inner: W) -> Result<Self, WriteError> {
+        let start = inner.stream_position()?;
+        inner.write_all(&[0; HEADER_LEN as usize])?;
+
+        Ok(ArchiveWriter {
+            inner,
+            start,
+            len: HEADER_LEN,
+            paths: Vec::new(),
+            files: Vec::new(),
+            unk: 0,
+        })
+    }
+
+    /// Sets the unidentified header field, see
+    /// [`Archive::unk`](crate::Archive::unk).
+    pub fn set_unk(&mut self, unk: u32) {
+        self.unk = unk;
+    }
+
+    fn pad(&mut self) -> io::Result<()> {
+        let r = self.len % ALIGN;
+        if r != 0 {
+            io::copy(&mut io::repeat(0).take(ALIGN - r), &mut self.inner)?;
+            self.len += ALIGN - r;
+        }
+        Ok(())
+    }
+
+    pub fn file<'a>(&'a mut self, path: &CStr) -> Result<ArchiveFileWriter<'a, W>, WriteError> {
+        if path.is_empty() {
+            return Err(WriteError::EmptyPath);
+        }
+        if self.files.len() >= u32::MAX as usize {
+            return Err(WriteError::TooManyFiles);
+        }
+        self.pad()?;
+
+        let path_off = self.paths.len() as u64;
+        self.paths.extend_from_slice(path.to_bytes_with_nul());
+        let start = self.len;
+
+        Ok(ArchiveFileWriter {
+            archive: self,
+            path_off,
+            start,
+            len: 0,
+            unk: 0,
+            timestamp: 0,
+            checksum: 0,
+        })
+    }
+
+    /// Writes the path table and file records, fills in the header and
+    /// returns `inner` positioned at the end of the archive.
+    pub fn finish(mut self) -> Result<W, WriteError> {
+        self.pad()?;
+        let paths_off = self.len;
+        self.inner.write_all(&self.paths[..])?;
+        self.len += self.paths.len() as u64;
+
+        self.pad()?;
+        let info_off = self.len;
+        for f in self.files.iter() {
+            self.inner.write_all(&f.checksum.to_le_bytes()[..])?;
+            self.inner.write_all(&f.path_off.to_le_bytes()[..])?;
+            self.inner.write_all(&f.unk.to_le_bytes()[..])?;
+            self.inner.write_all(&f.size.to_le_bytes()[..])?;
+            self.inner.write_all(&f.offset.to_le_bytes()[..])?;
+            self.inner.write_all(&f.timestamp.to_le_bytes()[..])?;
+        }
+        self.len += self.files.len() as u64 * 48;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        header[0..8].copy_from_slice(b"FAFULLFS");
+        header[8..12].copy_from_slice(&(self.files.len() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&self.unk.to_le_bytes());
+        header[16..24].copy_from_slice(&paths_off.to_le_bytes());
+        header[24..32].copy_from_slice(&(self.paths.len() as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&info_off.to_le_bytes());
+
+        self.inner.seek(SeekFrom::Start(self.start))?;
+        self.inner.write_all(&header[..])?;
+        self.inner.seek(SeekFrom::Start(self.start + self.len))?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<'a, W: Write + Seek> ArchiveFileWriter<'a, W> {
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+    }
+
+    /// Sets the stored checksum, which is otherwise left zeroed. Its
+    /// algorithm is unknown, so this is only useful for copying the
+    /// checksum of an unchanged entry.
+    pub fn set_checksum(&mut self, checksum: u64) {
+        self.checksum = checksum;
+    }
+
+    /// Sets the unidentified field of the file's record, see
+    /// [`Entry::unk`](crate::Entry::unk).
+    pub fn set_unk(&mut self, unk: u64) {
+        self.unk = unk;
+    }
+}
+
+impl<'a, W: Write + Seek> Write for ArchiveFileWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.archive.inner.write(buf)?;
+        self.len += written as u64;
+        self.archive.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.archive.inner.flush()
+    }
+}
+
+impl<'a, W: Write + Seek> Drop for ArchiveFileWriter<'a, W> {
+    fn drop(&mut self) {
+        self.archive.files.push(ArchiveFile {
+            checksum: self.checksum,
+            path_off: self.path_off,
+            unk: self.unk,
+            size: self.len,
+            // offsets are absolute from the start of the archive
+            offset: self.start,
+            timestamp: self.timestamp,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::Archive;
+
+    #[test]
+    fn reopens() {
+        let files: [(&CStr, &[u8], u64, u64, u64); 3] = [
+            (c"data/a.dat", b"first", 1, 2, 3),
+            (c"data/empty.dat", b"", 0, 0, 0),
+            (c"b.dat", &[0xff; 0x25], u64::MAX, 5, 6),
+        ];
+        // a prefix before the archive, which offsets don't count
+        let mut out = Cursor::new(vec![0xee; 3]);
+        out.set_position(3);
+        let mut writer = ArchiveWriter::new(out).unwrap();
+        writer.set_unk(0x1234_5678);
+        for &(path, contents, unk, timestamp, checksum) in files.iter() {
+            let mut file = writer.file(path).unwrap();
+            file.set_unk(unk);
+            file.set_timestamp(timestamp);
+            file.set_checksum(checksum);
+            file.write_all(contents).unwrap();
+        }
+        let out = writer.finish().unwrap();
+        assert_eq!(out.position(), out.get_ref().len() as u64);
+
+        let mut archive = Archive::from_bytes(&out.get_ref()[3..]).unwrap();
+        assert_eq!(archive.unk(), 0x1234_5678);
+        assert_eq!(archive.len(), files.len());
+        for (index, &(path, contents, unk, timestamp, checksum)) in files.iter().enumerate() {
+            let entry = archive.entry(index).unwrap();
+            assert_eq!(entry.path().unwrap(), path);
+            assert_eq!(entry.unk(), unk);
+            assert_eq!(entry.timestamp(), timestamp);
+            assert_eq!(entry.checksum(), checksum);
+            assert_eq!(entry.size(), contents.len() as u64);
+            assert_eq!(entry.offset() % ALIGN, 0);
+
+            assert_eq!(archive.file_bytes(index).unwrap().unwrap(), contents);
+            let mut data = Vec::new();
+            archive
+                .get_file(index)
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, contents);
+        }
+    }
+
+    #[test]
+    fn empty_path() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(matches!(writer.file(c""), Err(WriteError::EmptyPath)));
+    }
+}
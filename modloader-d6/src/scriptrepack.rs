@@ -1,127 +1,96 @@
-use makaikit_dsarcfl::{Archive, ArchiveWriter};
-use std::{
-    collections::HashSet,
-    ffi::CString,
-    fs::File,
-    io::{self, Read, Seek},
-    path::Path,
-};
-use walkdir::WalkDir;
+use makaikit_dsarcfl::{Archive, ArchiveEditor};
+use makaikit_vfs::OverlayVfs;
+use std::io::{self, Read, Seek, Write};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ScriptRepackError {
-    #[error("A file in the source archive has an invalid name")]
-    InvalidSourceFileName,
-
     #[error("IO error")]
     Io(#[from] io::Error),
 
-    #[error("DSARC FL read error")]
-    DsArcFlRead(#[from] makaikit_dsarcfl::ReadError),
-
-    #[error("DSARC FL write error")]
-    DsArcFlWrite(#[from] makaikit_dsarcfl::WriteError),
+    #[error("DSARC FL edit error")]
+    DsArcFlEdit(#[from] makaikit_dsarcfl::EditError),
 }
 
-/// Creates a new DSARC FL buffer from the source Archive
-pub fn repack_scripts<R>(
+/// Writes a new DSARC FL archive from the source Archive to `out`, with every
+/// lua script in `scripts` compiled in by file name. A script in an earlier
+/// layer replaces one with the same name in a later layer
+pub fn repack_scripts<R, W>(
     source: &mut Archive<R>,
-    replacement_paths: &[&Path],
-) -> Result<Vec<u8>, ScriptRepackError>
+    scripts: &mut OverlayVfs<'_>,
+    out: W,
+) -> Result<W, ScriptRepackError>
 where
     R: Read + Seek,
+    W: Write + Seek,
 {
-    let mut new_archive = ArchiveWriter::new();
-
-    let mut used_names = HashSet::new();
+    let mut editor = ArchiveEditor::new(source)?;
 
-    // First, insert replacements into the archive
-    for repl_path in replacement_paths {
-        log::debug!("Walking {repl_path:?} for lua scripts");
-        for entry in WalkDir::new(repl_path) {
-            match entry {
-                Err(e) => {
-                    log::warn!("Unable to access script file in mod dir: {}", e);
-                }
-                Ok(entry) => {
-                    let ft = entry.file_type();
-                    if !ft.is_file() {
-                        continue;
-                    }
-                    if !entry
-                        .path()
-                        .extension()
-                        .and_then(|ext| if ext == "lua" { Some(()) } else { None })
-                        .is_some()
-                    {
-                        continue;
-                    }
-
-                    let mut name_string = entry
-                        .path()
-                        .file_name()
-                        .unwrap()
-                        .to_string_lossy()
-                        .into_owned();
-                    name_string.make_ascii_lowercase();
-                    name_string.replace_range(name_string.rfind(".lua").unwrap().., ".lub");
-                    if used_names.contains(&name_string) {
-                        log::warn!("Skipping {:?} for script.dat replacement because the name {} was already inserted", entry.path(), name_string);
-                        continue;
-                    }
+    log::debug!("Listing lua scripts");
+    // Walk in reverse so that earlier mods in the load order win
+    for layer in scripts.layers_mut().rev() {
+        for path in layer.list("")? {
+            if !path.ends_with(".lua") {
+                continue;
+            }
 
-                    log::debug!("Adding script {:?}", entry.path());
+            let mut name_string = path.rsplit('/').next().unwrap().to_owned();
+            name_string.make_ascii_lowercase();
+            name_string.replace_range(name_string.rfind(".lua").unwrap().., ".lub");
 
-                    let name_cstring = CString::new(name_string.as_bytes()).unwrap();
-                    let mut file_writer = new_archive.file(&name_cstring).unwrap();
-                    let mut file_reader = match File::open(entry.path()) {
-                        Err(e) => {
-                            log::warn!("Unable to access script file in mod dir: {}", e);
-                            continue;
-                        }
-                        Ok(f) => f,
-                    };
-                    std::io::copy(&mut file_reader, &mut file_writer)?;
-                    drop(file_writer);
+            let mut data = Vec::new();
+            if let Err(e) = layer
+                .open(&path)
+                .and_then(|mut file| file.read_to_end(&mut data))
+            {
+                log::warn!("Unable to access script file in mod dir: {}", e);
+                continue;
+            }
 
-                    used_names.insert(name_string);
-                }
+            log::debug!("Adding script {:?}", path);
+            if let Err(e) = editor.put(&name_string, data) {
+                log::warn!("Skipping {:?} for script.dat replacement: {}", path, e);
             }
         }
-        log::debug!("Walked {repl_path:?} for lua scripts");
     }
-    log::debug!("Walked mod directories");
+    log::debug!("Listed lua scripts");
 
-    // Second, look for files to reinsert from the original archive that aren't already replaced
-    for i in 0..source.len() {
-        let mut orig_file = source.get_file(i).unwrap()?;
-        let orig_name = match orig_file.name().to_str() {
-            Err(_) => {
-                return Err(ScriptRepackError::InvalidSourceFileName);
-            }
-            Ok(name) => name,
-        }
-        .to_owned();
-        if !used_names.contains(&orig_name) {
-            let name_cstring = match CString::new(orig_name.as_bytes()) {
-                Err(_) => {
-                    return Err(ScriptRepackError::InvalidSourceFileName);
-                }
-                Ok(name) => name,
-            };
-            let mut file_writer = new_archive.file(&name_cstring)?;
-            match std::io::copy(&mut orig_file, &mut file_writer) {
-                Err(e) => {
-                    log::error!("Fatal: unable to copy original file {name_cstring:?}");
-                    return Err(ScriptRepackError::Io(e));
-                }
-                _ => {}
-            }
-            drop(file_writer);
-            log::debug!("Inserted original script {orig_name}");
+    Ok(editor.write_to(out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use makaikit_dsarcfl::ArchiveWriter;
+
+    use super::*;
+
+    fn archive(files: &[(&std::ffi::CStr, &[u8])]) -> Archive<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new();
+        for &(name, contents) in files {
+            writer.file(name).unwrap().write_all(contents).unwrap();
         }
+        Archive::open(Cursor::new(writer.finish().unwrap())).unwrap()
     }
-    log::debug!("Walked source archive for unreplaced scripts");
 
-    Ok(new_archive.finish()?)
+    #[test]
+    fn earlier_layers_win() {
+        let mut source = archive(&[(c"a.lub", b"original"), (c"b.lub", b"original")]);
+        let mut scripts = OverlayVfs::new();
+        scripts.push(archive(&[(c"A.lua", b"first")]));
+        scripts.push(archive(&[(c"a.lua", b"second"), (c"b.lua", b"second")]));
+
+        let out = repack_scripts(&mut source, &mut scripts, Cursor::new(Vec::new())).unwrap();
+        let mut out = Archive::open(Cursor::new(out.into_inner())).unwrap();
+        assert_eq!(out.len(), 2);
+        for (name, expected) in [("a.lub", &b"first"[..]), ("b.lub", b"second")] {
+            let mut contents = Vec::new();
+            out.open_by_name(name)
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert_eq!(contents, expected);
+        }
+    }
 }
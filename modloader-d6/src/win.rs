@@ -2,6 +2,7 @@
     borrow::Borrow,
     ffi::{CStr, CString},
     fs::File,
+    io::BufWriter,
     path::PathBuf,
     sync::RwLock,
 };
@@ -15,6 +16,7 @@
     encode::pattern::PatternEncoder,
     Config,
 };
+use makaikit_vfs::{DirVfs, OverlayVfs, Vfs};
 use winapi::{
     shared::{
         minwindef::{DWORD, HMODULE, LPVOID},
@@ -32,6 +34,8 @@
 
 lazy_static! {
     static ref MOD_LOAD_ORDER: RwLock<Vec<PathBuf>> = RwLock::default();
+    /// Generated files, then each mod's `files` directory in load order
+    static ref MOD_FILES: RwLock<OverlayVfs<'static>> = RwLock::default();
 }
 
 fn aob_search(buf: &[u8], start: LPVOID, dist: usize) -> Option<LPVOID> {
@@ -239,38 +243,13 @@ extern "fastcall" fn hook_get_file_from_archive_2(
         let path_str = CStr::from_ptr(a3 as *const i8);
         let safe_path_str = path_str.to_string_lossy();
 
-        // _generated replacements loading
-        {
-            let dest_path = PathBuf::from("mods/_generated").join(safe_path_str.as_ref());
-            match std::fs::metadata(&dest_path) {
-                Ok(metadata) => {
-                    if metadata.is_file() {
-                        let new_path = CString::new(dest_path.to_string_lossy().as_ref()).unwrap();
-                        log::debug!("Using generated {:?}", new_path);
-                        return (std::mem::transmute::<_, PfnGetFileFromArchive2>(
-                            GET_FILE_FROM_ARCHIVE_2_TRAMPOLINE,
-                        ))(a1, a2, new_path.as_ptr(), a4, 0, a6, a7);
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        // Mod loading
-        let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
-        for mod_path in mod_load_order.iter() {
-            let dest_path = mod_path.join("files").join(safe_path_str.as_ref());
-            match std::fs::metadata(&dest_path) {
-                Ok(metadata) => {
-                    if metadata.is_file() {
-                        let new_path = CString::new(dest_path.to_string_lossy().as_ref()).unwrap();
-                        return (std::mem::transmute::<_, PfnGetFileFromArchive2>(
-                            GET_FILE_FROM_ARCHIVE_2_TRAMPOLINE,
-                        ))(a1, a2, new_path.as_ptr(), a4, 0, a6, a7);
-                    }
-                }
-                _ => {}
-            }
+        // _generated replacements and mod loading
+        if let Some(dest_path) = MOD_FILES.read().unwrap().host_path(safe_path_str.as_ref()) {
+            let new_path = CString::new(dest_path.to_string_lossy().as_ref()).unwrap();
+            log::debug!("Using {:?}", new_path);
+            return (std::mem::transmute::<_, PfnGetFileFromArchive2>(
+                GET_FILE_FROM_ARCHIVE_2_TRAMPOLINE,
+            ))(a1, a2, new_path.as_ptr(), a4, 0, a6, a7);
         }
         return (std::mem::transmute::<_, PfnGetFileFromArchive2>(
             GET_FILE_FROM_ARCHIVE_2_TRAMPOLINE,
@@ -317,16 +296,21 @@ fn init_mod_load_order() {
     log::info!("Mod load order: {:?}", mod_load_order.borrow());
 }
 
+fn init_mod_files() {
+    let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
+    let mut mod_files = MOD_FILES.write().unwrap();
+    mod_files.push(DirVfs::new("mods/_generated"));
+    for mod_path in mod_load_order.iter() {
+        mod_files.push(DirVfs::new(mod_path.join("files")));
+    }
+}
+
 fn init_script_repack() {
     let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
-    let replacement_paths = mod_load_order
-        .iter()
-        .map(|p| p.join("scripts"))
-        .collect::<Vec<_>>();
-    let repl_paths_refs = replacement_paths
-        .iter()
-        .map(|p| p.as_path())
-        .collect::<Vec<_>>();
+    let mut scripts = OverlayVfs::new();
+    for mod_path in mod_load_order.iter() {
+        scripts.push(DirVfs::new(mod_path.join("scripts")));
+    }
 
     let src_file = match File::open("data/script.dat") {
         Err(e) => {
@@ -345,16 +329,6 @@ fn init_script_repack() {
     };
     log::debug!("Loaded data/script.dat");
 
-    let new_archive_buf =
-        match crate::scriptrepack::repack_scripts(&mut src_archive, &repl_paths_refs[..]) {
-            Err(e) => {
-                log::error!("Unable to repack scripts: {e}");
-                return;
-            }
-            Ok(a) => a,
-        };
-    log::debug!("Repacked scripts to buffer");
-
     match std::fs::create_dir_all("mods/_generated/data") {
         Err(e) => {
             log::error!("Unable to create mods/_generated/data directory: {e}");
@@ -364,11 +338,21 @@ fn init_script_repack() {
     }
     log::debug!("Created _generated/data path for script.dat");
 
-    match std::fs::write("mods/_generated/data/script.dat", new_archive_buf) {
+    let out_file = match File::create("mods/_generated/data/script.dat") {
         Err(e) => {
             log::error!("Unable to create mods/_generated/data/script.dat: {e}");
             return;
         }
+        Ok(f) => BufWriter::new(f),
+    };
+
+    match crate::scriptrepack::repack_scripts(&mut src_archive, &mut scripts, out_file) {
+        Err(e) => {
+            log::error!("Unable to repack scripts: {e}");
+            // don't leave a partial archive for the file hook to pick up
+            let _ = std::fs::remove_file("mods/_generated/data/script.dat");
+            return;
+        }
         _ => {}
     }
     log::debug!("Wrote generated script.dat");
@@ -381,6 +365,7 @@ fn init() {
     log::info!("Mod load order initialized");
     init_script_repack();
     log::info!("Script repack generated");
+    init_mod_files();
 
     unsafe {
         let base_handle = GetModuleHandleA(std::ptr::null());
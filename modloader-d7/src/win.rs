@@ -1,11 +1,10 @@
 use std::{
     borrow::Borrow,
-    collections::HashMap,
+    collections::BTreeMap,
     ffi::{CStr, CString},
     fs::File,
-    io::{BufWriter, Read, Seek},
+    io::BufWriter,
     path::{Path, PathBuf},
-    str::FromStr,
     sync::RwLock,
 };
 
@@ -20,6 +19,7 @@
 };
 use makaikit_databases_d7::*;
 use makaikit_databases_serde::DatabaseRecord;
+use makaikit_vfs::{DirVfs, OverlayVfs, Vfs};
 use winapi::{
     shared::{
         minwindef::{DWORD, HMODULE, LPVOID},
@@ -33,6 +33,8 @@
 
 lazy_static! {
     static ref MOD_LOAD_ORDER: RwLock<Vec<PathBuf>> = RwLock::default();
+    /// Generated files, then each mod's `files` directory in load order
+    static ref MOD_FILES: RwLock<OverlayVfs<'static>> = RwLock::default();
 }
 
 static mut NMPL_FILE_CFILEMGR_OPENCMN_TRAMPOLINE: *const () = std::ptr::null();
@@ -77,56 +79,22 @@ extern "fastcall" fn hook_nmpl_file_cfilemgr_opencmn(
         let path_str = CStr::from_ptr(path as *const i8);
         let safe_path_str = path_str.to_string_lossy();
 
-        // _generated replacements loading
-        {
-            let dest_path = PathBuf::from("mods/_generated").join(safe_path_str.as_ref());
-            let mut dest_path_str = dest_path.to_string_lossy().into_owned();
-            dest_path_str = dest_path_str.replace("\\", "/");
-            match std::fs::metadata(&dest_path) {
-                Ok(metadata) => {
-                    if metadata.is_file() {
-                        let new_path = CString::new(dest_path_str).unwrap();
-                        log::info!("Using generated {:?}", new_path);
-                        return (std::mem::transmute::<_, PfnNmplFileCFileMgrOpenCmn>(
-                            NMPL_FILE_CFILEMGR_OPENCMN_TRAMPOLINE,
-                        ))(
-                            this,
-                            a2,
-                            new_path.as_ptr(),
-                            e_device_type,
-                            0,
-                            e_file_wait_mode,
-                            e_cache_mode,
-                        );
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        // Mod loading
-        let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
-        for mod_path in mod_load_order.iter() {
-            let dest_path = mod_path.join("files").join(safe_path_str.as_ref());
-            match std::fs::metadata(&dest_path) {
-                Ok(metadata) => {
-                    if metadata.is_file() {
-                        let new_path = CString::new(dest_path.to_string_lossy().as_ref()).unwrap();
-                        return (std::mem::transmute::<_, PfnNmplFileCFileMgrOpenCmn>(
-                            NMPL_FILE_CFILEMGR_OPENCMN_TRAMPOLINE,
-                        ))(
-                            this,
-                            a2,
-                            new_path.as_ptr(),
-                            e_device_type,
-                            0,
-                            e_file_wait_mode,
-                            e_cache_mode,
-                        );
-                    }
-                }
-                _ => {}
-            }
+        // _generated replacements and mod loading
+        if let Some(dest_path) = MOD_FILES.read().unwrap().host_path(safe_path_str.as_ref()) {
+            let dest_path_str = dest_path.to_string_lossy().replace("\\", "/");
+            let new_path = CString::new(dest_path_str).unwrap();
+            log::info!("Using {:?}", new_path);
+            return (std::mem::transmute::<_, PfnNmplFileCFileMgrOpenCmn>(
+                NMPL_FILE_CFILEMGR_OPENCMN_TRAMPOLINE,
+            ))(
+                this,
+                a2,
+                new_path.as_ptr(),
+                e_device_type,
+                0,
+                e_file_wait_mode,
+                e_cache_mode,
+            );
         }
         return (std::mem::transmute::<_, PfnNmplFileCFileMgrOpenCmn>(
             NMPL_FILE_CFILEMGR_OPENCMN_TRAMPOLINE,
@@ -181,56 +149,40 @@ fn init_mod_load_order() {
     log::info!("Mod load order: {:?}", mod_load_order.borrow());
 }
 
-#[derive(Debug)]
-enum RecordIdentifier {
-    Id(i32),
-    EnumName(String),
+fn init_mod_files() {
+    let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
+    let mut mod_files = MOD_FILES.write().unwrap();
+    mod_files.push(DirVfs::new("mods/_generated"));
+    for mod_path in mod_load_order.iter() {
+        mod_files.push(DirVfs::new(mod_path.join("files")));
+    }
 }
 
-fn parse_file_stem(base: &str) -> Option<RecordIdentifier> {
-    if base.is_empty() {
-        return None;
+/// `e` followed by each of its sources, for logging.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(&format!(": {e}"));
+        source = e.source();
     }
-
-    let split = match base.split_once("_") {
-        Some(v) => v,
-        None => return Some(RecordIdentifier::EnumName(base.to_owned())),
-    };
-    match <i32 as FromStr>::from_str(split.0) {
-        Ok(v) => return Some(RecordIdentifier::Id(v)),
-        Err(_) => {}
-    }
-    Some(RecordIdentifier::EnumName(base.to_owned()))
+    message
 }
 
-fn repack_database<R: Read + Seek, T: DatabaseRecord>(
-    archive: &mut makaikit_fafullfs::Archive<R>,
-    name: &str,
-) where
+fn repack_database<V: Vfs + ?Sized, T: DatabaseRecord>(source: &mut V, name: &str)
+where
     T: serde::de::DeserializeOwned + serde::Serialize + std::fmt::Debug,
 {
     let mod_load_order = MOD_LOAD_ORDER.read().unwrap();
-    let mut entry = None;
-    let archive_len = archive.len();
-
-    log::debug!("Finding db {name} in archive");
-    for index in 0..archive_len {
-        let this_entry = archive.get_file(index).unwrap().unwrap();
-        let entry_name = this_entry.path().to_str().unwrap();
-        if entry_name == format!("data/database/{}.dat", name) {
-            entry = Some(index);
-            log::info!("Found {entry_name}");
-            break;
-        }
-    }
-    let entry = match entry {
-        None => {
-            log::error!("DB {name} not found");
+    let source_path = format!("data/database/{name}.dat");
+    let real_entry = match source.open(&source_path) {
+        Err(e) => {
+            log::error!("DB {name} not found: {e}");
             return;
         }
-        Some(v) => v,
+        Ok(f) => f,
     };
-    let real_entry = archive.get_file(entry).unwrap().unwrap();
+    log::info!("Found {source_path}");
 
     let db_records = match makaikit_databases_serde::decode_database::<_, T>(real_entry) {
         Err(e) => {
@@ -240,188 +192,37 @@ fn repack_database<R: Read + Seek, T: DatabaseRecord>(
         Ok(o) => o,
     };
 
-    let mut db_map = HashMap::<i32, T>::new();
+    let mut db_map = BTreeMap::<i32, T>::new();
     for record in db_records {
         db_map.insert(record.database_id(), record);
     }
 
     for entry in mod_load_order.iter() {
-        let database_root_path = entry.join("databases").join(name);
-        let db_read_dir = match database_root_path.read_dir() {
-            Err(e) => {
-                log::debug!(
-                    "Unable to open {}, moving on: {}",
-                    database_root_path.display(),
-                    e
-                );
-                continue;
-            }
-            Ok(o) => o,
-        };
-
-        for read_dir_entry_result in db_read_dir {
-            let read_dir_entry = match read_dir_entry_result {
-                Err(_) => continue,
-                Ok(o) => o,
-            };
-            let mut read_dir_file = match File::open(read_dir_entry.path()) {
-                Err(_) => continue,
-                Ok(o) => o,
-            };
-            let dir_entry_path = read_dir_entry.path();
-            let file_name = dir_entry_path.file_name().unwrap().to_string_lossy();
-            let file_stem = dir_entry_path.file_stem().unwrap().to_string_lossy();
-
-            if file_name.ends_with(".patch.json") {
-                log::info!("JSON Patch {}", dir_entry_path.display());
-                let name_identifier =
-                    match parse_file_stem(&file_name.strip_suffix(".patch.json").unwrap()) {
-                        None => {
-                            log::error!(
-                                "File stem {} does not identify a database record",
-                                file_stem
-                            );
-                            continue;
-                        }
-                        Some(i) => i,
-                    };
-                let orig_record_maybe = match name_identifier {
-                    RecordIdentifier::Id(id) => db_map.get(&id),
-                    RecordIdentifier::EnumName(ref name) => db_map.iter().find_map(|e| {
-                        if e.1.database_enum_name() == name {
-                            Some(e.1)
-                        } else {
-                            None
-                        }
-                    }),
-                };
-                let orig_record = match orig_record_maybe {
-                    None => {
-                        log::error!("Applying {} patch {} for ID {:?} failed because there is no source record", name, dir_entry_path.display(), name_identifier);
-                        continue;
-                    }
-                    Some(r) => r,
-                };
-                let mut record_json = match serde_json::to_value(orig_record) {
-                    Err(e) => {
-                        log::error!("Failed to convert {name} record to json");
-                        continue;
-                    }
-                    Ok(v) => v,
-                };
-                let json_patch =
-                    match serde_json::from_reader::<_, json_patch::Patch>(&mut read_dir_file) {
-                        Err(e) => {
-                            log::error!(
-                                "File {} is not a proper json: {}",
-                                dir_entry_path.display(),
-                                e
-                            );
-                            continue;
-                        }
-                        Ok(o) => o,
-                    };
-                match json_patch::patch(&mut record_json, &json_patch.0) {
-                    Err(e) => {
-                        log::error!(
-                            "JSON patch {} application failed: {}",
-                            dir_entry_path.display(),
-                            e
-                        );
-                        continue;
-                    }
-                    _ => {}
+        let mut mod_vfs = DirVfs::new(entry);
+        let applied = modfile::apply_mod_files(&mut db_map, &mut mod_vfs, name, |path, result| {
+            let path = entry.join(path);
+            match result {
+                Ok(modfile::ModFileKind::JsonPatch) => log::info!("JSON Patch {}", path.display()),
+                Ok(modfile::ModFileKind::MergePatch) => {
+                    log::info!("Merge patch {}", path.display())
+                }
+                Ok(modfile::ModFileKind::Replacement) => {
+                    log::info!("Record replacement {}", path.display())
                 }
-                let new_record = match serde_json::from_value::<T>(record_json) {
-                    Err(e) => {
-                        log::error!(
-                            "Parsing record after applying JSON patch {} failed: {}",
-                            dir_entry_path.display(),
-                            e
-                        );
-                        continue;
-                    }
-                    Ok(v) => v,
-                };
-                db_map.insert(new_record.database_id(), new_record);
-            } else if file_name.ends_with(".merge.json") {
-                log::info!("Merge patch {}", dir_entry_path.display());
-                let name_identifier =
-                    match parse_file_stem(&file_name.strip_suffix(".merge.json").unwrap()) {
-                        None => {
-                            log::error!(
-                                "File stem {} does not identify a database record",
-                                file_stem
-                            );
-                            continue;
-                        }
-                        Some(i) => i,
-                    };
-                let orig_record_maybe = match name_identifier {
-                    RecordIdentifier::Id(id) => db_map.get(&id),
-                    RecordIdentifier::EnumName(ref name) => db_map.iter().find_map(|e| {
-                        if e.1.database_enum_name() == name {
-                            Some(e.1)
-                        } else {
-                            None
-                        }
-                    }),
-                };
-                let orig_record = match orig_record_maybe {
-                    None => {
-                        log::error!("Applying {} merge patch {} for ID {:?} failed because there is no source record", name, dir_entry_path.display(), name_identifier);
-                        continue;
-                    }
-                    Some(r) => r,
-                };
-                let mut record_json = match serde_json::to_value(orig_record) {
-                    Err(e) => {
-                        log::error!("Failed to convert {name} record to json");
-                        continue;
-                    }
-                    Ok(v) => v,
-                };
-                let merge_patch =
-                    match serde_json::from_reader::<_, serde_json::Value>(&mut read_dir_file) {
-                        Err(e) => {
-                            log::error!(
-                                "File {} is not a proper json: {}",
-                                dir_entry_path.display(),
-                                e
-                            );
-                            continue;
-                        }
-                        Ok(o) => o,
-                    };
-                json_patch::merge(&mut record_json, &merge_patch);
-                let new_record = match serde_json::from_value::<T>(record_json) {
-                    Err(e) => {
-                        log::error!(
-                            "Parsing record after applying merge patch {} failed: {}",
-                            dir_entry_path.display(),
-                            e
-                        );
-                        continue;
-                    }
-                    Ok(v) => v,
-                };
-                db_map.insert(new_record.database_id(), new_record);
-            } else if file_name.ends_with(".json") {
-                log::info!("Record replacement {}", dir_entry_path.display());
-                let record = match serde_json::from_reader::<_, T>(&mut read_dir_file) {
-                    Err(e) => {
-                        log::error!(
-                            "File {} is not a proper {}: {}",
-                            dir_entry_path.display(),
-                            name,
-                            e
-                        );
-                        continue;
-                    }
-                    Ok(o) => o,
-                };
-                db_map.insert(record.database_id(), record);
+                Err(e) => log::error!(
+                    "Applying {} database file {} failed: {}",
+                    name,
+                    path.display(),
+                    error_chain(&e)
+                ),
             }
+        });
+        if let Err(e) = applied {
+            log::debug!(
+                "Unable to open {}, moving on: {}",
+                entry.join(format!("databases/{name}")).display(),
+                e
+            );
         }
     }
 
@@ -469,42 +270,45 @@ fn repack_database<R: Read + Seek, T: DatabaseRecord>(
 
 fn repack_databases() {
     let mut archive = makaikit_fafullfs::Archive::open(File::open("data.dat").unwrap()).unwrap();
-    repack_database::<_, ActData>(&mut archive, "act");
-    repack_database::<_, ActEffectData>(&mut archive, "acteffect");
-    repack_database::<_, ActFeatureData>(&mut archive, "actfeature");
-    repack_database::<_, ActLearnData>(&mut archive, "actlearn");
-    repack_database::<_, ActMapData>(&mut archive, "actmap");
-    repack_database::<_, AiData>(&mut archive, "ai");
-    repack_database::<_, AiPartsData>(&mut archive, "aiparts");
-    repack_database::<_, AnimeData>(&mut archive, "animedata");
-    repack_database::<_, AnimeBankData>(&mut archive, "animebank");
-    repack_database::<_, ArchiveData>(&mut archive, "archive");
-    repack_database::<_, AreaData>(&mut archive, "area");
-    repack_database::<_, BattleFlagData>(&mut archive, "battleflag");
-    repack_database::<_, BgmData>(&mut archive, "bgm");
-    repack_database::<_, BuData>(&mut archive, "bu");
-    repack_database::<_, CharaClassData>(&mut archive, "characlass");
-    repack_database::<_, CharaData>(&mut archive, "character");
-    repack_database::<_, CharaFeatureData>(&mut archive, "charafeature");
-    repack_database::<_, CharaZukanData>(&mut archive, "charazukan");
-    repack_database::<_, CheatSettingData>(&mut archive, "cheatsetting");
-    repack_database::<_, DopingData>(&mut archive, "doping");
-    repack_database::<_, DrinkData>(&mut archive, "drink");
-    repack_database::<_, DungeonData>(&mut archive, "dungeon");
-    repack_database::<_, EvilityData>(&mut archive, "evility");
-    repack_database::<_, HospitalGachaData>(&mut archive, "hospitalgacha");
-    repack_database::<_, InnocentAffinityData>(&mut archive, "innocentaffinity");
-    repack_database::<_, InnocentData>(&mut archive, "innocent");
-    repack_database::<_, ItemData>(&mut archive, "item");
-    repack_database::<_, ItemCustomData>(&mut archive, "itemcustom");
-    repack_database::<_, ItemFeatureData>(&mut archive, "itemfeature");
-    repack_database::<_, ItemShopLineupData>(&mut archive, "itemshoplineup");
-    repack_database::<_, ItemStrengthenData>(&mut archive, "itemstrengthen");
-    repack_database::<_, ItemTypeData>(&mut archive, "itemtype");
-    repack_database::<_, JobData>(&mut archive, "job");
-    repack_database::<_, StageData>(&mut archive, "stage");
-    repack_database::<_, StringData>(&mut archive, "string");
-    repack_database::<_, WishData>(&mut archive, "wish");
+    // mods and previously generated databases are patched on top of the
+    // originals, not on top of each other
+    let archive = &mut archive as &mut dyn Vfs;
+    repack_database::<_, ActData>(archive, "act");
+    repack_database::<_, ActEffectData>(archive, "acteffect");
+    repack_database::<_, ActFeatureData>(archive, "actfeature");
+    repack_database::<_, ActLearnData>(archive, "actlearn");
+    repack_database::<_, ActMapData>(archive, "actmap");
+    repack_database::<_, AiData>(archive, "ai");
+    repack_database::<_, AiPartsData>(archive, "aiparts");
+    repack_database::<_, AnimeData>(archive, "animedata");
+    repack_database::<_, AnimeBankData>(archive, "animebank");
+    repack_database::<_, ArchiveData>(archive, "archive");
+    repack_database::<_, AreaData>(archive, "area");
+    repack_database::<_, BattleFlagData>(archive, "battleflag");
+    repack_database::<_, BgmData>(archive, "bgm");
+    repack_database::<_, BuData>(archive, "bu");
+    repack_database::<_, CharaClassData>(archive, "characlass");
+    repack_database::<_, CharaData>(archive, "character");
+    repack_database::<_, CharaFeatureData>(archive, "charafeature");
+    repack_database::<_, CharaZukanData>(archive, "charazukan");
+    repack_database::<_, CheatSettingData>(archive, "cheatsetting");
+    repack_database::<_, DopingData>(archive, "doping");
+    repack_database::<_, DrinkData>(archive, "drink");
+    repack_database::<_, DungeonData>(archive, "dungeon");
+    repack_database::<_, EvilityData>(archive, "evility");
+    repack_database::<_, HospitalGachaData>(archive, "hospitalgacha");
+    repack_database::<_, InnocentAffinityData>(archive, "innocentaffinity");
+    repack_database::<_, InnocentData>(archive, "innocent");
+    repack_database::<_, ItemData>(archive, "item");
+    repack_database::<_, ItemCustomData>(archive, "itemcustom");
+    repack_database::<_, ItemFeatureData>(archive, "itemfeature");
+    repack_database::<_, ItemShopLineupData>(archive, "itemshoplineup");
+    repack_database::<_, ItemStrengthenData>(archive, "itemstrengthen");
+    repack_database::<_, ItemTypeData>(archive, "itemtype");
+    repack_database::<_, JobData>(archive, "job");
+    repack_database::<_, StageData>(archive, "stage");
+    repack_database::<_, StringData>(archive, "string");
+    repack_database::<_, WishData>(archive, "wish");
 }
 
 fn init() {
@@ -514,6 +318,7 @@ fn init() {
     log::info!("Mod load order initialized");
     // Disgaea 7 no longer has a special script archive!
     repack_databases();
+    init_mod_files();
 
     unsafe {
         LoadLibraryA(b"NmplDLL.dll\0".as_ptr() as *const i8);
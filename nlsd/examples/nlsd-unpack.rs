@@ -1,12 +1,19 @@
-use std::{fs::File, path::PathBuf};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
 
 use anyhow::Context;
 use clap::Parser;
-use makaikit_nlsd::NlsdRead;
+use makaikit_nlsd::{NlsdRead, NlsdSection};
 
 #[derive(Parser)]
 struct Args {
     input: PathBuf,
+    /// Write a single file with the loop marked, instead of one per section
+    #[arg(short, long)]
+    looped: bool,
 }
 
 fn main() -> Result<(), anyhow::Error> {
@@ -32,33 +39,32 @@ fn main() -> Result<(), anyhow::Error> {
         7 => "ogg",
         _ => unreachable!(),
     };
-    match nlsd.section_begin() {
-        Ok(Some(mut section)) => {
-            std::io::copy(
-                &mut section,
-                &mut File::create(&format!("{}_0.{}", file_stem, extension))?,
-            )?;
-        }
-        Err(e) => {
-            return Err(e).with_context(|| format!("Unable to read beginning of NLSD"));
-        }
-        _ => {}
+    if args.looped {
+        let out_path = format!("{}.{}", file_stem, extension);
+        let out = BufWriter::new(
+            File::create(&out_path).with_context(|| format!("Unable to create {}", out_path))?,
+        );
+        nlsd.export(out)
+            .context("Unable to export NLSD")?
+            .flush()
+            .with_context(|| format!("Unable to write {}", out_path))?;
+        return Ok(());
     }
-    std::io::copy(
-        &mut nlsd.section_middle()?,
-        &mut File::create(&format!("{}_1.{}", file_stem, extension))?,
-    )?;
-    match nlsd.section_end() {
-        Ok(Some(mut section)) => {
-            std::io::copy(
-                &mut section,
-                &mut File::create(&format!("{}_2.{}", file_stem, extension))?,
-            )?;
-        }
-        Err(e) => {
-            return Err(e).with_context(|| format!("Unable to read beginning of NLSD"));
-        }
-        _ => {}
+    for (section, range) in nlsd.sections() {
+        let index = match section {
+            NlsdSection::Begin => 0,
+            NlsdSection::Middle => 1,
+            NlsdSection::End => 2,
+        };
+        let out_path = format!("{}_{}.{}", file_stem, index, extension);
+        std::io::copy(
+            &mut nlsd
+                .section_at(range)
+                .with_context(|| format!("Unable to read {:?} section of NLSD", section))?,
+            &mut File::create(&out_path)
+                .with_context(|| format!("Unable to create {}", out_path))?,
+        )
+        .with_context(|| format!("Unable to write {}", out_path))?;
     }
     Ok(())
 }
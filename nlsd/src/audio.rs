@@ -0,0 +1,52 @@
+//! Just enough WAV and Ogg Vorbis parsing to fill in an NLSD header.
+
+use crate::{ogg, wav};
+
+/// The NLSD format of a section.
+pub(crate) const FORMAT_WAV: u32 = 5;
+pub(crate) const FORMAT_OGG: u32 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AudioInfo {
+    pub format: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Samples per channel
+    pub samples: u64,
+}
+
+/// Why a section's header couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ProbeError {
+    UnrecognizedFormat,
+    Invalid(&'static str),
+}
+
+pub(crate) fn read_u16(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(at..at + 2)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_u32(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(at..at + 4)?.try_into().unwrap()))
+}
+
+pub(crate) fn read_u64(buf: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(buf.get(at..at + 8)?.try_into().unwrap()))
+}
+
+/// The NLSD format `data` would be stored as, judging by its magic.
+pub(crate) fn detect(data: &[u8]) -> Option<u32> {
+    match data.get(..4)? {
+        b"RIFF" if data.get(8..12) == Some(b"WAVE") => Some(FORMAT_WAV),
+        b"OggS" => Some(FORMAT_OGG),
+        _ => None,
+    }
+}
+
+pub(crate) fn probe(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    match detect(data) {
+        Some(FORMAT_WAV) => wav::Wav::parse(data)?.info(),
+        Some(FORMAT_OGG) => ogg::probe(data),
+        _ => Err(ProbeError::UnrecognizedFormat),
+    }
+}
@@ -0,0 +1,417 @@
+//! Conversion between NLSD sections and a single audio file that marks the
+//! loop, which audio editors keep track of.
+//!
+//! WAV files mark the loop with a `smpl` chunk. Ogg Vorbis files mark it
+//! with `LOOPSTART` and `LOOPLENGTH` comments, and are chained streams, one
+//! per section, since they can't be cut without re-encoding them.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::{
+    audio::{self, ProbeError, FORMAT_OGG, FORMAT_WAV},
+    ogg, wav, NlsdRead, ReadError,
+};
+
+const LOOP_START: &str = "LOOPSTART";
+const LOOP_LENGTH: &str = "LOOPLENGTH";
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ExportError {
+    #[error("Read error")]
+    Read(#[from] ReadError),
+
+    #[error("The {0} section is not a WAV or Ogg Vorbis file")]
+    UnrecognizedFormat(&'static str),
+
+    #[error("The {0} section has an invalid {1}")]
+    InvalidHeader(&'static str, &'static str),
+
+    #[error("The sections differ in {0}")]
+    Mismatch(&'static str),
+
+    #[error("Unsupported value for {0}: {1}")]
+    UnsupportedValue(&'static str, String),
+
+    #[error("The sections are too large to join")]
+    TooLarge,
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ImportError {
+    #[error("Not a WAV or Ogg Vorbis file")]
+    UnrecognizedFormat,
+
+    #[error("Invalid {0}")]
+    InvalidHeader(&'static str),
+
+    #[error("Unsupported value for {0}: {1}")]
+    UnsupportedValue(&'static str, String),
+
+    #[error("The loop from sample {start} to {end} is outside the {samples} samples")]
+    InvalidLoop { start: u64, end: u64, samples: u64 },
+
+    #[error(
+        "The loop from sample {start} to {end} does not fall between chained Ogg streams, \
+        which is the only place Ogg Vorbis can be split"
+    )]
+    UnalignedLoop { start: u64, end: u64 },
+}
+
+type Sections<'a> = [(&'static str, Option<&'a [u8]>); 3];
+
+impl<R> NlsdRead<R>
+where
+    R: Read + Seek,
+{
+    /// Joins the sections into a single WAV or Ogg Vorbis file with the loop
+    /// marked, which [`NlsdWrite::set_looped`](crate::NlsdWrite::set_looped)
+    /// splits again.
+    pub fn export<W: Write>(&mut self, mut out: W) -> Result<W, ExportError> {
+        let mut data: [Option<Vec<u8>>; 3] = Default::default();
+        for (section, range) in self.sections() {
+            // an empty outro is no outro
+            if range.is_empty() {
+                continue;
+            }
+            let mut section_data = Vec::new();
+            self.section_at(range)?.read_to_end(&mut section_data)?;
+            data[section as usize] = Some(section_data);
+        }
+        let sections = [
+            ("intro", data[0].as_deref()),
+            ("loop", data[1].as_deref()),
+            ("outro", data[2].as_deref()),
+        ];
+
+        let exported = match self.format() {
+            FORMAT_WAV => export_wav(sections)?,
+            FORMAT_OGG => export_ogg(sections)?,
+            _ => unreachable!(),
+        };
+        out.write_all(&exported)?;
+        Ok(out)
+    }
+}
+
+fn export_error(name: &'static str) -> impl Fn(ProbeError) -> ExportError {
+    move |e| match e {
+        ProbeError::UnrecognizedFormat => ExportError::UnrecognizedFormat(name),
+        ProbeError::Invalid(field) => ExportError::InvalidHeader(name, field),
+    }
+}
+
+fn export_wav(sections: Sections<'_>) -> Result<Vec<u8>, ExportError> {
+    let mut fmt: Option<&[u8]> = None;
+    let mut data = Vec::new();
+    let mut samples = [0; 3];
+    for (i, (name, section)) in sections.into_iter().enumerate() {
+        let Some(section) = section else {
+            continue;
+        };
+        let wav = wav::Wav::parse(section).map_err(export_error(name))?;
+        if !wav.is_uncompressed() {
+            return Err(ExportError::UnsupportedValue(
+                "WAV encoding",
+                format!("{:#x}", wav.format_tag()),
+            ));
+        }
+        if fmt.is_some_and(|fmt| fmt != wav.fmt) {
+            return Err(ExportError::Mismatch("WAV format"));
+        }
+        fmt = Some(wav.fmt);
+        data.extend(wav.data);
+        samples[i] = wav.samples();
+    }
+
+    let loop_start = samples[0];
+    let loop_end = loop_start + samples[1];
+    wav::write(fmt.unwrap(), &data, Some((loop_start, loop_end))).ok_or(ExportError::TooLarge)
+}
+
+fn export_ogg(sections: Sections<'_>) -> Result<Vec<u8>, ExportError> {
+    let mut streams = Vec::new();
+    let mut samples = [0; 3];
+    for (i, (name, section)) in sections.into_iter().enumerate() {
+        let Some(section) = section else {
+            continue;
+        };
+        let section_streams = ogg::streams(section).map_err(export_error(name))?;
+        samples[i] = section_streams.iter().map(ogg::Stream::samples).sum();
+        streams.extend(section_streams);
+    }
+
+    for stream in streams.iter() {
+        if stream.sample_rate() != streams[0].sample_rate() {
+            return Err(ExportError::Mismatch("sample rate"));
+        }
+        if stream.channels() != streams[0].channels() {
+            return Err(ExportError::Mismatch("channel count"));
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut serials = Vec::with_capacity(streams.len());
+    for (i, stream) in streams.iter().enumerate() {
+        let mut comments = stream.comments();
+        if i == 0 {
+            comments.remove(LOOP_START);
+            comments.remove(LOOP_LENGTH);
+            comments.push(LOOP_START, &samples[0].to_string());
+            comments.push(LOOP_LENGTH, &samples[1].to_string());
+        }
+        // the links of a chain must have different serial numbers
+        let mut serial = stream.serial();
+        while serials.contains(&serial) {
+            serial = serial.wrapping_add(1);
+        }
+        serials.push(serial);
+        stream.write(&mut out, serial, &comments);
+    }
+    Ok(out)
+}
+
+/// Checks that `start..end` is a non-empty range within `samples`.
+fn check_loop(start: u64, end: u64, samples: u64) -> Result<(), ImportError> {
+    if start >= end || end > samples {
+        return Err(ImportError::InvalidLoop {
+            start,
+            end,
+            samples,
+        });
+    }
+    Ok(())
+}
+
+/// Splits a WAV or Ogg Vorbis file with the loop marked into intro, loop
+/// and outro sections.
+pub(crate) fn import(data: &[u8]) -> Result<[Option<Vec<u8>>; 3], ImportError> {
+    match audio::detect(data) {
+        Some(FORMAT_WAV) => import_wav(data),
+        Some(FORMAT_OGG) => import_ogg(data),
+        _ => Err(ImportError::UnrecognizedFormat),
+    }
+}
+
+fn import_error(e: ProbeError) -> ImportError {
+    match e {
+        ProbeError::UnrecognizedFormat => ImportError::UnrecognizedFormat,
+        ProbeError::Invalid(field) => ImportError::InvalidHeader(field),
+    }
+}
+
+fn import_wav(data: &[u8]) -> Result<[Option<Vec<u8>>; 3], ImportError> {
+    let wav = wav::Wav::parse(data).map_err(import_error)?;
+    if !wav.is_uncompressed() {
+        return Err(ImportError::UnsupportedValue(
+            "WAV encoding",
+            format!("{:#x}", wav.format_tag()),
+        ));
+    }
+    let samples = wav.samples();
+    let (start, end) = wav.sample_loop().unwrap_or((0, samples));
+    check_loop(start, end, samples)?;
+
+    let block_align = wav.block_align() as usize;
+    let section = |from: u64, to: u64| {
+        if from == to {
+            return None;
+        }
+        let data = &wav.data[from as usize * block_align..to as usize * block_align];
+        // a part of a file that fit in RIFF fits too
+        Some(wav::write(wav.fmt, data, None).unwrap())
+    };
+    Ok([
+        section(0, start),
+        section(start, end),
+        section(end, samples),
+    ])
+}
+
+fn import_ogg(data: &[u8]) -> Result<[Option<Vec<u8>>; 3], ImportError> {
+    let streams = ogg::streams(data).map_err(import_error)?;
+    let samples: u64 = streams.iter().map(ogg::Stream::samples).sum();
+
+    let comments = streams[0].comments();
+    let parse_comment = |name: &'static str| {
+        comments
+            .get(name)
+            .map(|value| value.trim().parse::<u64>())
+            .transpose()
+            .map_err(|_| ImportError::InvalidHeader(name))
+    };
+    let start = parse_comment(LOOP_START)?.unwrap_or(0);
+    let end = match parse_comment(LOOP_LENGTH)? {
+        Some(length) => start.saturating_add(length),
+        None => samples,
+    };
+    check_loop(start, end, samples)?;
+
+    // the loop has to start and end where one stream ends and the next
+    // starts
+    let mut middle_start = None;
+    let mut end_start = None;
+    let mut position = 0;
+    for (i, stream) in streams.iter().enumerate() {
+        if position == start {
+            middle_start.get_or_insert(i);
+        }
+        if position == end {
+            end_start.get_or_insert(i);
+        }
+        position += stream.samples();
+    }
+    if position == end {
+        end_start.get_or_insert(streams.len());
+    }
+    let (Some(middle_start), Some(end_start)) = (middle_start, end_start) else {
+        return Err(ImportError::UnalignedLoop { start, end });
+    };
+
+    let section = |range: std::ops::Range<usize>| {
+        if range.is_empty() {
+            return None;
+        }
+        let mut out = Vec::new();
+        for i in range {
+            let mut comments = streams[i].comments();
+            if i == 0 {
+                comments.remove(LOOP_START);
+                comments.remove(LOOP_LENGTH);
+            }
+            streams[i].write(&mut out, streams[i].serial(), &comments);
+        }
+        Some(out)
+    };
+    Ok([
+        section(0..middle_start),
+        section(middle_start..end_start),
+        section(end_start..streams.len()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::NlsdWrite;
+
+    fn pack(sections: [Option<Vec<u8>>; 3]) -> NlsdRead<Cursor<Vec<u8>>> {
+        let mut nlsd = NlsdWrite::new(Vec::new());
+        let [begin, middle, end] = sections;
+        if let Some(begin) = begin {
+            nlsd.set_begin(begin);
+        }
+        nlsd.set_middle(middle.unwrap());
+        if let Some(end) = end {
+            nlsd.set_end(end);
+        }
+        NlsdRead::open(Cursor::new(nlsd.finish().unwrap())).unwrap()
+    }
+
+    fn unpack(nlsd: &mut NlsdRead<Cursor<Vec<u8>>>) -> [Option<Vec<u8>>; 3] {
+        let mut data: [Option<Vec<u8>>; 3] = Default::default();
+        for (section, range) in nlsd.sections() {
+            let mut section_data = Vec::new();
+            nlsd.section_at(range)
+                .unwrap()
+                .read_to_end(&mut section_data)
+                .unwrap();
+            data[section as usize] = Some(section_data);
+        }
+        data
+    }
+
+    /// Exports the sections and imports them back, checking they come back
+    /// the same. Returns the exported file.
+    fn round_trip(sections: [Option<Vec<u8>>; 3]) -> Vec<u8> {
+        let mut nlsd = pack(sections.clone());
+        let loop_samples = nlsd.loop_samples().unwrap();
+        let exported = nlsd.export(Vec::new()).unwrap();
+
+        let mut imported = NlsdWrite::new(Vec::new());
+        imported.set_looped(&exported).unwrap();
+        let mut imported = NlsdRead::open(Cursor::new(imported.finish().unwrap())).unwrap();
+        assert_eq!(imported.format(), nlsd.format());
+        assert_eq!(imported.samples(), nlsd.samples());
+        assert_eq!(imported.loop_samples().unwrap(), loop_samples);
+        assert_eq!(unpack(&mut imported), unpack(&mut nlsd));
+        exported
+    }
+
+    fn wav(samples: usize, fill: u8) -> Vec<u8> {
+        wav::write(&wav::pcm_fmt(2, 44100), &vec![fill; samples * 4], None).unwrap()
+    }
+
+    #[test]
+    fn wav_round_trip() {
+        let exported = round_trip([Some(wav(100, 1)), Some(wav(300, 2)), Some(wav(50, 3))]);
+        let exported = wav::Wav::parse(&exported).unwrap();
+        assert_eq!(exported.samples(), 450);
+        assert_eq!(exported.sample_loop(), Some((100, 400)));
+
+        let exported = round_trip([None, Some(wav(300, 2)), None]);
+        let exported = wav::Wav::parse(&exported).unwrap();
+        assert_eq!(exported.sample_loop(), Some((0, 300)));
+
+        // an intro without an outro reads with an empty outro
+        round_trip([Some(wav(100, 1)), Some(wav(300, 2)), None]);
+    }
+
+    #[test]
+    fn ogg_round_trip() {
+        let exported = round_trip([
+            Some(ogg::test_stream(1, 44100, 2, &["TITLE=a"], &[100])),
+            Some(
+                [
+                    ogg::test_stream(2, 44100, 2, &[], &[100, 200]),
+                    ogg::test_stream(3, 44100, 2, &[], &[100]),
+                ]
+                .concat(),
+            ),
+            Some(ogg::test_stream(4, 44100, 2, &[], &[50])),
+        ]);
+        let streams = ogg::streams(&exported).unwrap();
+        assert_eq!(streams.len(), 4);
+        let comments = streams[0].comments();
+        assert_eq!(comments.get(LOOP_START), Some("100"));
+        assert_eq!(comments.get(LOOP_LENGTH), Some("300"));
+        assert_eq!(comments.get("TITLE"), Some("a"));
+
+        round_trip([None, Some(ogg::test_stream(1, 44100, 1, &[], &[300])), None]);
+    }
+
+    #[test]
+    fn ogg_loop_between_streams() {
+        let exported = [
+            ogg::test_stream(1, 44100, 2, &["LOOPSTART=50", "LOOPLENGTH=100"], &[100]),
+            ogg::test_stream(2, 44100, 2, &[], &[100]),
+        ]
+        .concat();
+        assert!(matches!(
+            import(&exported),
+            Err(ImportError::UnalignedLoop {
+                start: 50,
+                end: 150
+            })
+        ));
+    }
+
+    #[test]
+    fn loop_outside_samples() {
+        let exported = wav::write(&wav::pcm_fmt(2, 44100), &[0; 400], Some((50, 200))).unwrap();
+        assert!(matches!(
+            import(&exported),
+            Err(ImportError::InvalidLoop {
+                start: 50,
+                end: 200,
+                samples: 100
+            })
+        ));
+    }
+}
@@ -0,0 +1,490 @@
+//! Ogg Vorbis streams, which format 7 NLSD sections are.
+//!
+//! Nothing here decodes audio. Streams are only split and joined between
+//! pages, and their comment headers rewritten.
+
+use crate::audio::{read_u32, read_u64, AudioInfo, ProbeError, FORMAT_OGG};
+
+const OGG_CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
+
+const FLAG_CONTINUED: u8 = 0x01;
+const FLAG_BOS: u8 = 0x02;
+
+#[derive(Clone, Copy)]
+struct Page<'a> {
+    header: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> Page<'a> {
+    fn flags(&self) -> u8 {
+        self.header[5]
+    }
+
+    /// `u64::MAX` if no packet ends on this page.
+    fn granule(&self) -> u64 {
+        read_u64(self.header, 6).unwrap()
+    }
+
+    fn serial(&self) -> u32 {
+        read_u32(self.header, 14).unwrap()
+    }
+
+    fn segments(&self) -> &'a [u8] {
+        &self.header[27..]
+    }
+}
+
+/// Iterates over the pages of an Ogg stream, stopping at anything that isn't
+/// one.
+fn pages(data: &[u8]) -> impl Iterator<Item = Page<'_>> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        if data.get(pos..pos + 4)? != b"OggS" {
+            return None;
+        }
+        let segments = *data.get(pos + 26)? as usize;
+        let table = data.get(pos + 27..pos + 27 + segments)?;
+        let header_len = 27 + segments;
+        let body_len: usize = table.iter().map(|&len| len as usize).sum();
+        let header = &data[pos..pos + header_len];
+        let body = data.get(pos + header_len..pos + header_len + body_len)?;
+        pos += header_len + body_len;
+        Some(Page { header, body })
+    })
+}
+
+fn write_page(
+    out: &mut Vec<u8>,
+    flags: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    segments: &[u8],
+    body: &[u8],
+) {
+    let start = out.len();
+    out.extend(b"OggS\0");
+    out.push(flags);
+    out.extend(granule.to_le_bytes());
+    out.extend(serial.to_le_bytes());
+    out.extend(sequence.to_le_bytes());
+    out.extend([0; 4]);
+    out.push(segments.len() as u8);
+    out.extend(segments);
+    out.extend(body);
+    // the Ogg CRC is CRC-32/CKSUM without the final inversion
+    let crc = OGG_CRC.checksum(&out[start..]) ^ 0xffff_ffff;
+    out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Splits packets into pages, returning the next page sequence number.
+/// `flags` are set on the first page.
+fn write_packets(
+    out: &mut Vec<u8>,
+    flags: u8,
+    serial: u32,
+    mut sequence: u32,
+    granule: u64,
+    packets: &[&[u8]],
+) -> u32 {
+    // (lacing value, whether it ends its packet)
+    let mut lacing = Vec::new();
+    for packet in packets {
+        lacing.extend(std::iter::repeat_n((255u8, false), packet.len() / 255));
+        lacing.push(((packet.len() % 255) as u8, true));
+    }
+    let body = packets.concat();
+
+    let mut body_pos = 0;
+    let mut flags = flags;
+    for page_lacing in lacing.chunks(255) {
+        let segments: Vec<u8> = page_lacing.iter().map(|&(len, _)| len).collect();
+        let body_len: usize = segments.iter().map(|&len| len as usize).sum();
+        let ends_packet = page_lacing.iter().any(|&(_, ends)| ends);
+        write_page(
+            out,
+            flags,
+            if ends_packet { granule } else { u64::MAX },
+            serial,
+            sequence,
+            &segments,
+            &body[body_pos..body_pos + body_len],
+        );
+        body_pos += body_len;
+        sequence += 1;
+        flags = if page_lacing.last().unwrap().1 {
+            0
+        } else {
+            FLAG_CONTINUED
+        };
+    }
+    sequence
+}
+
+/// The user comments of a Vorbis comment header.
+pub(crate) struct Comments {
+    vendor: Vec<u8>,
+    comments: Vec<Vec<u8>>,
+}
+
+impl Comments {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.get(..7)? != b"\x03vorbis" {
+            return None;
+        }
+        let mut pos = 7usize;
+        let mut take = |len: usize| {
+            let taken = packet.get(pos..pos.checked_add(len)?)?;
+            pos += len;
+            Some(taken)
+        };
+        let vendor_len = read_u32(take(4)?, 0)? as usize;
+        let vendor = take(vendor_len)?.to_vec();
+        let count = read_u32(take(4)?, 0)?;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let len = read_u32(take(4)?, 0)? as usize;
+            comments.push(take(len)?.to_vec());
+        }
+        Some(Comments { vendor, comments })
+    }
+
+    fn to_packet(&self) -> Vec<u8> {
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend((self.vendor.len() as u32).to_le_bytes());
+        packet.extend(&self.vendor);
+        packet.extend((self.comments.len() as u32).to_le_bytes());
+        for comment in self.comments.iter() {
+            packet.extend((comment.len() as u32).to_le_bytes());
+            packet.extend(comment);
+        }
+        // framing bit
+        packet.push(1);
+        packet
+    }
+
+    /// Splits a comment into its field name and value.
+    fn field(comment: &[u8]) -> Option<(&[u8], &[u8])> {
+        let split = comment.iter().position(|&b| b == b'=')?;
+        Some((&comment[..split], &comment[split + 1..]))
+    }
+
+    /// The value of the first comment named `name`, ignoring case as Vorbis
+    /// does.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.comments.iter().find_map(|comment| {
+            let (field, value) = Self::field(comment)?;
+            if field.eq_ignore_ascii_case(name.as_bytes()) {
+                std::str::from_utf8(value).ok()
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.comments.retain(|comment| {
+            Self::field(comment)
+                .is_none_or(|(field, _)| !field.eq_ignore_ascii_case(name.as_bytes()))
+        });
+    }
+
+    pub fn push(&mut self, name: &str, value: &str) {
+        self.comments
+            .push(format!("{}={}", name, value).into_bytes());
+    }
+}
+
+/// A logical Ogg Vorbis stream, one link of a chained file.
+pub(crate) struct Stream<'a> {
+    pages: Vec<Page<'a>>,
+    ident: Vec<u8>,
+    comment: Vec<u8>,
+    setup: Vec<u8>,
+    /// Index of the first page after the headers
+    audio_start: usize,
+}
+
+impl<'a> Stream<'a> {
+    fn parse(pages: Vec<Page<'a>>) -> Result<Self, ProbeError> {
+        // the three header packets end on a page of their own, before any
+        // audio
+        let mut packets = Vec::with_capacity(3);
+        let mut packet = Vec::new();
+        let mut audio_start = None;
+        'pages: for (index, page) in pages.iter().enumerate() {
+            let mut body_pos = 0;
+            for (segment, &len) in page.segments().iter().enumerate() {
+                packet.extend(&page.body[body_pos..body_pos + len as usize]);
+                body_pos += len as usize;
+                if len == 255 {
+                    continue;
+                }
+                packets.push(std::mem::take(&mut packet));
+                if packets.len() == 3 {
+                    if segment + 1 != page.segments().len() {
+                        return Err(ProbeError::Invalid("Vorbis setup header"));
+                    }
+                    audio_start = Some(index + 1);
+                    break 'pages;
+                }
+            }
+        }
+        let audio_start = audio_start.ok_or(ProbeError::Invalid("Vorbis headers"))?;
+        let setup = packets.pop().unwrap();
+        let comment = packets.pop().unwrap();
+        let ident = packets.pop().unwrap();
+
+        if ident.len() < 16 || &ident[..7] != b"\x01vorbis" {
+            return Err(ProbeError::Invalid("Vorbis identification header"));
+        }
+        if Comments::parse(&comment).is_none() {
+            return Err(ProbeError::Invalid("Vorbis comment header"));
+        }
+        if setup.get(..7) != Some(b"\x05vorbis") {
+            return Err(ProbeError::Invalid("Vorbis setup header"));
+        }
+
+        Ok(Stream {
+            pages,
+            ident,
+            comment,
+            setup,
+            audio_start,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.ident[11] as u16
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        read_u32(&self.ident, 12).unwrap()
+    }
+
+    /// The last granule position, which is the number of samples decoded by
+    /// the end of the stream.
+    pub fn samples(&self) -> u64 {
+        self.pages
+            .iter()
+            .map(Page::granule)
+            .rfind(|&granule| granule != u64::MAX)
+            .unwrap_or(0)
+    }
+
+    pub fn comments(&self) -> Comments {
+        Comments::parse(&self.comment).unwrap()
+    }
+
+    pub fn info(&self) -> AudioInfo {
+        AudioInfo {
+            format: FORMAT_OGG,
+            sample_rate: self.sample_rate(),
+            channels: self.channels(),
+            samples: self.samples(),
+        }
+    }
+
+    /// Writes the stream out under `serial`, with its comments replaced by
+    /// `comments`.
+    pub fn write(&self, out: &mut Vec<u8>, serial: u32, comments: &Comments) {
+        let sequence = write_packets(out, FLAG_BOS, serial, 0, 0, &[&self.ident]);
+        let sequence = write_packets(
+            out,
+            0,
+            serial,
+            sequence,
+            0,
+            &[&comments.to_packet(), &self.setup],
+        );
+
+        // audio pages keep their contents but are renumbered to follow the
+        // rewritten headers
+        for (index, page) in self.pages[self.audio_start..].iter().enumerate() {
+            write_page(
+                out,
+                page.flags() & !FLAG_BOS,
+                page.granule(),
+                serial,
+                sequence + index as u32,
+                page.segments(),
+                page.body,
+            );
+        }
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.pages[0].serial()
+    }
+}
+
+/// Splits a chained Ogg file into its logical streams, which must follow
+/// each other rather than being interleaved.
+pub(crate) fn streams(data: &[u8]) -> Result<Vec<Stream<'_>>, ProbeError> {
+    let mut streams = Vec::new();
+    let mut stream_pages: Vec<Page<'_>> = Vec::new();
+    let mut len = 0;
+    for page in pages(data) {
+        len += page.header.len() + page.body.len();
+        if page.flags() & FLAG_BOS != 0 && !stream_pages.is_empty() {
+            streams.push(Stream::parse(std::mem::take(&mut stream_pages))?);
+        }
+        if stream_pages
+            .first()
+            .is_some_and(|first| first.serial() != page.serial())
+        {
+            return Err(ProbeError::Invalid("Ogg stream, which is multiplexed"));
+        }
+        stream_pages.push(page);
+    }
+    if stream_pages.is_empty() {
+        return Err(ProbeError::Invalid("Ogg page"));
+    }
+    if len != data.len() {
+        return Err(ProbeError::Invalid("Ogg page"));
+    }
+    streams.push(Stream::parse(stream_pages)?);
+    Ok(streams)
+}
+
+pub(crate) fn probe(data: &[u8]) -> Result<AudioInfo, ProbeError> {
+    let streams = streams(data)?;
+    let mut info = streams[0].info();
+    info.samples = streams.iter().map(Stream::samples).sum();
+    Ok(info)
+}
+
+/// An Ogg Vorbis stream with the given headers and one audio page per
+/// granule position, whose packets aren't real audio.
+#[cfg(test)]
+pub(crate) fn test_stream(
+    serial: u32,
+    sample_rate: u32,
+    channels: u8,
+    comments: &[&str],
+    granules: &[u64],
+) -> Vec<u8> {
+    let mut ident = b"\x01vorbis".to_vec();
+    ident.extend(0u32.to_le_bytes());
+    ident.push(channels);
+    ident.extend(sample_rate.to_le_bytes());
+    ident.extend([0; 12]);
+    ident.extend([0xb8, 1]);
+    let comments = Comments {
+        vendor: b"makaikit".to_vec(),
+        comments: comments.iter().map(|c| c.as_bytes().to_vec()).collect(),
+    };
+
+    let mut out = Vec::new();
+    let sequence = write_packets(&mut out, FLAG_BOS, serial, 0, 0, &[&ident]);
+    let mut sequence = write_packets(
+        &mut out,
+        0,
+        serial,
+        sequence,
+        0,
+        &[&comments.to_packet(), b"\x05vorbis\0"],
+    );
+    for (i, &granule) in granules.iter().enumerate() {
+        sequence = write_packets(&mut out, 0, serial, sequence, granule, &[&[i as u8; 10]]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_crc() {
+        // check value of the direct CRC-32 Ogg uses
+        assert_eq!(OGG_CRC.checksum(b"123456789") ^ 0xffff_ffff, 0x89a1_897f);
+
+        let mut out = Vec::new();
+        write_page(&mut out, FLAG_BOS, 0, 0x1234, 0, &[3], b"abc");
+        assert_eq!(
+            out,
+            [
+                0x4f, 0x67, 0x67, 0x53, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xa8, 0x87, 0xe9, 0x7a, 0x01, 0x03,
+                0x61, 0x62, 0x63,
+            ]
+        );
+    }
+
+    #[test]
+    fn lacing_of_whole_segments() {
+        let mut out = Vec::new();
+        let sequence = write_packets(&mut out, FLAG_BOS, 1, 0, 7, &[&[1; 255], &[2; 510]]);
+        assert_eq!(sequence, 1);
+        let pages: Vec<_> = pages(&out).collect();
+        assert_eq!(pages.len(), 1);
+        // a packet that fills its last segment ends with an empty one
+        assert_eq!(pages[0].segments(), [255, 0, 255, 255, 0]);
+        assert_eq!(pages[0].granule(), 7);
+        assert_eq!(pages[0].body, [[1; 255].as_slice(), &[2; 510]].concat());
+    }
+
+    #[test]
+    fn lacing_across_pages() {
+        // 255 full segments fill a page, leaving the packet's empty last
+        // segment for the next
+        let packet = vec![3; 255 * 255];
+        let mut out = Vec::new();
+        let sequence = write_packets(&mut out, FLAG_BOS, 1, 5, 7, &[&packet]);
+        assert_eq!(sequence, 7);
+        let pages: Vec<_> = pages(&out).collect();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].flags(), FLAG_BOS);
+        assert_eq!(pages[0].segments(), [255; 255]);
+        assert_eq!(pages[0].granule(), u64::MAX);
+        assert_eq!(pages[0].body, packet);
+        assert_eq!(pages[1].flags(), FLAG_CONTINUED);
+        assert_eq!(pages[1].segments(), [0]);
+        assert_eq!(pages[1].granule(), 7);
+        assert_eq!(read_u32(pages[1].header, 18), Some(6));
+    }
+
+    #[test]
+    fn chained_streams() {
+        let data = [
+            test_stream(1, 44100, 2, &["TITLE=a"], &[100, 200]),
+            test_stream(2, 44100, 2, &[], &[300]),
+        ]
+        .concat();
+        let streams = streams(&data).unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].serial(), 1);
+        assert_eq!(streams[0].samples(), 200);
+        assert_eq!(streams[0].comments().get("title"), Some("a"));
+        assert_eq!(streams[1].serial(), 2);
+        assert_eq!(streams[1].samples(), 300);
+
+        let info = probe(&data).unwrap();
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.samples, 500);
+
+        // unchanged streams are written back as they were
+        let mut out = Vec::new();
+        for stream in streams.iter() {
+            stream.write(&mut out, stream.serial(), &stream.comments());
+        }
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn multiplexed_streams() {
+        let mut data = test_stream(1, 44100, 2, &[], &[100]);
+        let other = test_stream(2, 44100, 2, &[], &[100]);
+        // the second stream's last page, without its BOS flag
+        let last_page = pages(&other).last().unwrap();
+        data.extend(last_page.header);
+        data.extend(last_page.body);
+        assert!(matches!(
+            streams(&data),
+            Err(ProbeError::Invalid("Ogg stream, which is multiplexed"))
+        ));
+    }
+}
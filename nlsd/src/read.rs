@@ -0,0 +1,450 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+    time::Duration,
+};
+
+use byteorder::{ReadBytesExt, LE};
+
+use crate::audio::{self, ProbeError};
+
+const HEADER_LEN: u64 = 0x18;
+
+pub struct NlsdRead<R> {
+    format: u32,
+    total_size: u32,
+    sample_rate: u16,
+    stereo: bool,
+    samples: u32,
+    middle_ofs: u32,
+    end_ofs: u32,
+    read: R,
+}
+
+/// The parts an NLSD is split into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NlsdSection {
+    /// Played once before the loop
+    Begin,
+    /// The loop
+    Middle,
+    /// Played once after the loop
+    End,
+}
+
+pub struct NlsdSectionRead<'a, R> {
+    parent: &'a mut NlsdRead<R>,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadError {
+    #[error("Unrecognized value for {0}: {1}")]
+    UnrecognizedValue(&'static str, String),
+
+    #[error(
+        "Section offsets {middle_ofs:#x} and {end_ofs:#x} are out of order or past the total size {total_size:#x}"
+    )]
+    InvalidOffsets {
+        middle_ofs: u32,
+        end_ofs: u32,
+        total_size: u32,
+    },
+
+    #[error("Total size {total_size:#x} is past the end of the {stream_len:#x} byte stream")]
+    Truncated { total_size: u32, stream_len: u64 },
+
+    #[error("The {section:?} section has an invalid {field}")]
+    InvalidSection {
+        section: NlsdSection,
+        field: &'static str,
+    },
+
+    #[error("Section {start:#x}..{end:#x} is not within the sections")]
+    SectionOutOfBounds { start: u64, end: u64 },
+
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+}
+
+impl<R> NlsdRead<R>
+where
+    R: Read + Seek,
+{
+    pub fn open(mut data: R) -> Result<Self, ReadError> {
+        let stream_len = data.seek(SeekFrom::End(0))?;
+        data.seek(SeekFrom::Start(0))?;
+
+        let format = data.read_u32::<LE>()?;
+        if format != 5 && format != 7 {
+            return Err(ReadError::UnrecognizedValue(
+                "format",
+                format!("{}", format),
+            ));
+        }
+        let total_size = data.read_u32::<LE>()?;
+        let sample_rate = data.read_u16::<LE>()?;
+        if sample_rate == 0 {
+            return Err(ReadError::UnrecognizedValue(
+                "sample_rate",
+                format!("{}", sample_rate),
+            ));
+        }
+        let stereo_byte = data.read_u8()?;
+        if stereo_byte > 1 {
+            return Err(ReadError::UnrecognizedValue(
+                "stereo",
+                format!("{}", stereo_byte),
+            ));
+        }
+        let unused_byte = data.read_u8()?;
+        if unused_byte != 0 {
+            return Err(ReadError::UnrecognizedValue(
+                "unused_byte",
+                format!("{}", unused_byte),
+            ));
+        }
+        let stereo = stereo_byte > 0;
+        let samples = data.read_u32::<LE>()?;
+        let middle_ofs = data.read_u32::<LE>()?;
+        let end_ofs = data.read_u32::<LE>()?;
+
+        if middle_ofs > end_ofs || end_ofs > total_size {
+            return Err(ReadError::InvalidOffsets {
+                middle_ofs,
+                end_ofs,
+                total_size,
+            });
+        }
+        if HEADER_LEN + total_size as u64 > stream_len {
+            return Err(ReadError::Truncated {
+                total_size,
+                stream_len,
+            });
+        }
+
+        Ok(NlsdRead {
+            format,
+            total_size,
+            sample_rate,
+            stereo,
+            samples,
+            middle_ofs,
+            end_ofs,
+            read: data,
+        })
+    }
+}
+
+impl<R> NlsdRead<R> {
+    pub fn has_start(&self) -> bool {
+        self.middle_ofs != 0
+    }
+
+    /// Whether the header marks an outro, by the original check. A file with
+    /// an intro but no outro reports an empty one, and one whose intro and
+    /// outro are the same size reports none.
+    pub fn has_end(&self) -> bool {
+        self.end_ofs as u64 + self.middle_ofs as u64 != self.total_size as u64
+    }
+
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+
+    pub fn sample_rate(&self) -> u16 {
+        self.sample_rate
+    }
+
+    pub fn stereo(&self) -> bool {
+        self.stereo
+    }
+
+    /// Samples per channel across all sections.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples as f64 / self.sample_rate as f64)
+    }
+
+    /// The position of the intro within the stream, if there is one.
+    pub fn begin_range(&self) -> Option<Range<u64>> {
+        self.has_start()
+            .then(|| HEADER_LEN..HEADER_LEN + self.middle_ofs as u64)
+    }
+
+    /// The position of the loop within the stream.
+    pub fn middle_range(&self) -> Range<u64> {
+        HEADER_LEN + self.middle_ofs as u64..HEADER_LEN + self.end_ofs as u64
+    }
+
+    /// The position of the outro within the stream, if there is one.
+    pub fn end_range(&self) -> Option<Range<u64>> {
+        self.has_end()
+            .then(|| HEADER_LEN + self.end_ofs as u64..HEADER_LEN + self.total_size as u64)
+    }
+
+    /// The sections present and their positions within the stream, in
+    /// order. Unlike the section readers this doesn't borrow the NLSD, so
+    /// each range can be passed to [`NlsdRead::section_at`] in turn.
+    pub fn sections(&self) -> impl Iterator<Item = (NlsdSection, Range<u64>)> {
+        [
+            self.begin_range().map(|r| (NlsdSection::Begin, r)),
+            Some((NlsdSection::Middle, self.middle_range())),
+            self.end_range().map(|r| (NlsdSection::End, r)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+}
+
+impl<R> NlsdRead<R>
+where
+    R: Read + Seek,
+{
+    /// Reads the bytes at `range` within the stream, which must lie within
+    /// the sections, such as one given by [`NlsdRead::sections`].
+    pub fn section_at(&mut self, range: Range<u64>) -> Result<NlsdSectionRead<'_, R>, ReadError> {
+        if range.start > range.end
+            || range.start < HEADER_LEN
+            || range.end > HEADER_LEN + self.total_size as u64
+        {
+            return Err(ReadError::SectionOutOfBounds {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        self.read.seek(SeekFrom::Start(range.start))?;
+        Ok(NlsdSectionRead {
+            len: range.end - range.start,
+            parent: self,
+            start: range.start,
+            pos: 0,
+        })
+    }
+
+    /// Where the loop starts and ends in samples, going by the intro and loop
+    /// sections' own headers rather than the NLSD header.
+    pub fn loop_samples(&mut self) -> Result<Range<u64>, ReadError> {
+        let mut samples = [0; 2];
+        for (section, range) in self.sections() {
+            if section == NlsdSection::End {
+                continue;
+            }
+            let mut data = Vec::new();
+            self.section_at(range)?.read_to_end(&mut data)?;
+            let info = audio::probe(&data).map_err(|e| ReadError::InvalidSection {
+                section,
+                field: match e {
+                    ProbeError::UnrecognizedFormat => "WAV or Ogg Vorbis header",
+                    ProbeError::Invalid(field) => field,
+                },
+            })?;
+            samples[section as usize] = info.samples;
+        }
+        Ok(samples[0]..samples[0] + samples[1])
+    }
+
+    pub fn section_begin<'a>(&'a mut self) -> Result<Option<NlsdSectionRead<'a, R>>, ReadError> {
+        match self.begin_range() {
+            None => Ok(None),
+            Some(range) => self.section_at(range).map(Some),
+        }
+    }
+
+    pub fn section_middle<'a>(&'a mut self) -> Result<NlsdSectionRead<'a, R>, ReadError> {
+        self.section_at(self.middle_range())
+    }
+
+    pub fn section_end<'a>(&'a mut self) -> Result<Option<NlsdSectionRead<'a, R>>, ReadError> {
+        match self.end_range() {
+            None => Ok(None),
+            Some(range) => self.section_at(range).map(Some),
+        }
+    }
+}
+
+impl<'a, R> Read for NlsdSectionRead<'a, R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read_len = buf.len().min(self.len as usize - self.pos as usize);
+        let actual_read = self.parent.read.read(&mut buf[..read_len])?;
+        self.pos += actual_read as u64;
+
+        Ok(actual_read)
+    }
+}
+
+impl<'a, R> Seek for NlsdSectionRead<'a, R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?
+        // stay within the section
+        .min(self.len);
+
+        self.parent
+            .read
+            .seek(SeekFrom::Start(self.start + new_pos))?;
+        self.pos = new_pos;
+
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A format 5 NLSD header followed by `total_size` bytes counting up.
+    fn nlsd(sample_rate: u16, middle_ofs: u32, end_ofs: u32, total_size: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(5u32.to_le_bytes());
+        data.extend(total_size.to_le_bytes());
+        data.extend(sample_rate.to_le_bytes());
+        data.extend([1, 0]);
+        data.extend(0u32.to_le_bytes());
+        data.extend(middle_ofs.to_le_bytes());
+        data.extend(end_ofs.to_le_bytes());
+        data.extend((0..total_size).map(|i| i as u8));
+        data
+    }
+
+    fn open(data: Vec<u8>) -> Result<NlsdRead<Cursor<Vec<u8>>>, ReadError> {
+        NlsdRead::open(Cursor::new(data))
+    }
+
+    #[test]
+    fn valid_header() {
+        let nlsd = open(nlsd(44100, 0x10, 0x30, 0x50)).unwrap();
+        assert_eq!(nlsd.begin_range(), Some(0x18..0x28));
+        assert_eq!(nlsd.middle_range(), 0x28..0x48);
+        assert_eq!(nlsd.end_range(), Some(0x48..0x68));
+    }
+
+    #[test]
+    fn offsets_out_of_order() {
+        assert!(matches!(
+            open(nlsd(44100, 0x30, 0x10, 0x40)),
+            Err(ReadError::InvalidOffsets {
+                middle_ofs: 0x30,
+                end_ofs: 0x10,
+                total_size: 0x40
+            })
+        ));
+    }
+
+    #[test]
+    fn offsets_past_total_size() {
+        assert!(matches!(
+            open(nlsd(44100, 0x10, 0x50, 0x40)),
+            Err(ReadError::InvalidOffsets {
+                middle_ofs: 0x10,
+                end_ofs: 0x50,
+                total_size: 0x40
+            })
+        ));
+    }
+
+    #[test]
+    fn total_size_past_stream() {
+        let mut data = nlsd(44100, 0x10, 0x30, 0x40);
+        data.truncate(0x18 + 0x3f);
+        assert!(matches!(
+            open(data),
+            Err(ReadError::Truncated {
+                total_size: 0x40,
+                stream_len: 0x57
+            })
+        ));
+    }
+
+    #[test]
+    fn zero_sample_rate() {
+        assert!(matches!(
+            open(nlsd(0, 0x10, 0x30, 0x40)),
+            Err(ReadError::UnrecognizedValue("sample_rate", _))
+        ));
+    }
+
+    #[test]
+    fn section_seek() {
+        let mut nlsd = open(nlsd(44100, 0x10, 0x30, 0x50)).unwrap();
+        let mut section = nlsd.section_middle().unwrap();
+        let mut buf = [0; 4];
+
+        assert_eq!(section.seek(SeekFrom::Start(4)).unwrap(), 4);
+        assert_eq!(section.stream_position().unwrap(), 4);
+        section.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x14, 0x15, 0x16, 0x17]);
+        assert_eq!(section.stream_position().unwrap(), 8);
+
+        assert_eq!(section.seek(SeekFrom::Current(-6)).unwrap(), 2);
+        section.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x12, 0x13, 0x14, 0x15]);
+
+        assert_eq!(section.seek(SeekFrom::End(-4)).unwrap(), 0x1c);
+        let mut rest = Vec::new();
+        section.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [0x2c, 0x2d, 0x2e, 0x2f]);
+    }
+
+    #[test]
+    fn section_seek_past_end() {
+        let mut nlsd = open(nlsd(44100, 0x10, 0x30, 0x50)).unwrap();
+        let mut section = nlsd.section_middle().unwrap();
+        let mut rest = Vec::new();
+
+        // seeking past the end stops at the end of the section
+        assert_eq!(section.seek(SeekFrom::Start(0x100)).unwrap(), 0x20);
+        assert_eq!(section.read_to_end(&mut rest).unwrap(), 0);
+        assert_eq!(section.seek(SeekFrom::End(1)).unwrap(), 0x20);
+        assert_eq!(section.seek(SeekFrom::Current(1)).unwrap(), 0x20);
+        assert_eq!(section.stream_position().unwrap(), 0x20);
+        assert_eq!(section.read_to_end(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn section_seek_negative() {
+        let mut nlsd = open(nlsd(44100, 0x10, 0x30, 0x50)).unwrap();
+        let mut section = nlsd.section_middle().unwrap();
+        section.seek(SeekFrom::Start(4)).unwrap();
+
+        for pos in [SeekFrom::Current(-5), SeekFrom::End(-0x21)] {
+            let e = section.seek(pos).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        // a failed seek leaves the position alone
+        assert_eq!(section.stream_position().unwrap(), 4);
+        let mut buf = [0; 1];
+        section.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x14]);
+    }
+}
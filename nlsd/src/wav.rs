@@ -0,0 +1,218 @@
+//! RIFF WAVE files, which format 5 NLSD sections are.
+
+use crate::audio::{read_u16, read_u32, AudioInfo, ProbeError, FORMAT_WAV};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Iterates over the chunks of a RIFF file as (id, contents) pairs.
+fn chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut pos = 12;
+    std::iter::from_fn(move || {
+        let id = data.get(pos..pos + 4)?;
+        let len = read_u32(data, pos + 4)? as usize;
+        let start = pos + 8;
+        let contents = data.get(start..start.checked_add(len)?)?;
+        // chunks are padded to an even length
+        pos = start + len + (len & 1);
+        Some((id, contents))
+    })
+}
+
+/// The chunks of a WAV file that matter for splitting and joining it.
+pub(crate) struct Wav<'a> {
+    pub fmt: &'a [u8],
+    pub data: &'a [u8],
+    fact_samples: Option<u32>,
+    smpl: Option<&'a [u8]>,
+}
+
+impl<'a> Wav<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ProbeError> {
+        let mut fmt = None;
+        let mut fact_samples = None;
+        let mut smpl = None;
+        let mut wav_data = None;
+        for (id, contents) in chunks(data) {
+            match id {
+                b"fmt " => fmt = Some(contents),
+                b"fact" => fact_samples = read_u32(contents, 0),
+                b"smpl" => smpl = Some(contents),
+                b"data" => wav_data = Some(contents),
+                _ => {}
+            }
+        }
+
+        let fmt = fmt
+            .filter(|fmt| fmt.len() >= 16)
+            .ok_or(ProbeError::Invalid("WAV fmt chunk"))?;
+        let data = wav_data.ok_or(ProbeError::Invalid("WAV data chunk"))?;
+        if read_u16(fmt, 12) == Some(0) {
+            return Err(ProbeError::Invalid("WAV block alignment"));
+        }
+        Ok(Wav {
+            fmt,
+            data,
+            fact_samples,
+            smpl,
+        })
+    }
+
+    pub fn format_tag(&self) -> u16 {
+        read_u16(self.fmt, 0).unwrap()
+    }
+
+    pub fn channels(&self) -> u16 {
+        read_u16(self.fmt, 2).unwrap()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        read_u32(self.fmt, 4).unwrap()
+    }
+
+    pub fn block_align(&self) -> u16 {
+        read_u16(self.fmt, 12).unwrap()
+    }
+
+    /// Whether every sample takes up `block_align` bytes, so the data can be
+    /// cut and joined at any sample.
+    pub fn is_uncompressed(&self) -> bool {
+        matches!(
+            self.format_tag(),
+            WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT | WAVE_FORMAT_EXTENSIBLE
+        )
+    }
+
+    pub fn samples(&self) -> u64 {
+        // compressed encodings give their length in a fact chunk
+        match self.fact_samples {
+            Some(samples) if !self.is_uncompressed() => samples as u64,
+            _ => (self.data.len() / self.block_align() as usize) as u64,
+        }
+    }
+
+    pub fn info(&self) -> Result<AudioInfo, ProbeError> {
+        Ok(AudioInfo {
+            format: FORMAT_WAV,
+            sample_rate: self.sample_rate(),
+            channels: self.channels(),
+            samples: self.samples(),
+        })
+    }
+
+    /// The first loop of the smpl chunk, as a start and an exclusive end in
+    /// samples.
+    pub fn sample_loop(&self) -> Option<(u64, u64)> {
+        let smpl = self.smpl?;
+        if read_u32(smpl, 28)? == 0 {
+            return None;
+        }
+        let start = read_u32(smpl, 36 + 8)? as u64;
+        let end = read_u32(smpl, 36 + 12)? as u64;
+        Some((start, end + 1))
+    }
+}
+
+/// Writes a WAV file with the given fmt chunk contents and samples, marking
+/// the `sample_loop` samples as a loop if there are any. Returns `None` if
+/// the file would be too large for RIFF.
+pub(crate) fn write(fmt: &[u8], data: &[u8], sample_loop: Option<(u64, u64)>) -> Option<Vec<u8>> {
+    let mut chunks: Vec<(&[u8], Vec<u8>)> = vec![(b"fmt ", fmt.to_vec())];
+    if let Some((start, end)) = sample_loop {
+        let sample_rate = read_u32(fmt, 4).unwrap().max(1);
+        let mut smpl = Vec::with_capacity(60);
+        for field in [
+            0,                                        // manufacturer
+            0,                                        // product
+            1_000_000_000 / sample_rate,              // sample period in nanoseconds
+            60,                                       // MIDI unity note
+            0,                                        // MIDI pitch fraction
+            0,                                        // SMPTE format
+            0,                                        // SMPTE offset
+            1,                                        // loop count
+            0,                                        // sampler data length
+            0,                                        // loop cue point id
+            0,                                        // loop type, forward
+            u32::try_from(start).ok()?,               // loop start
+            u32::try_from(end.checked_sub(1)?).ok()?, // loop end, inclusive
+            0,                                        // loop fraction
+            0,                                        // loop play count, infinite
+        ] {
+            smpl.extend(field.to_le_bytes());
+        }
+        chunks.push((b"smpl", smpl));
+    }
+
+    let mut out = Vec::with_capacity(data.len() + 128);
+    out.extend(b"RIFF\0\0\0\0WAVE");
+    for (id, contents) in chunks
+        .iter()
+        .map(|(id, contents)| (*id, &contents[..]))
+        .chain(std::iter::once((&b"data"[..], data)))
+    {
+        out.extend(id);
+        out.extend(u32::try_from(contents.len()).ok()?.to_le_bytes());
+        out.extend(contents);
+        if contents.len() & 1 != 0 {
+            out.push(0);
+        }
+    }
+    let riff_len = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(out)
+}
+
+/// The fmt chunk contents of 16-bit PCM.
+#[cfg(test)]
+pub(crate) fn pcm_fmt(channels: u16, sample_rate: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let mut fmt = Vec::with_capacity(16);
+    fmt.extend(WAVE_FORMAT_PCM.to_le_bytes());
+    fmt.extend(channels.to_le_bytes());
+    fmt.extend(sample_rate.to_le_bytes());
+    fmt.extend((sample_rate * block_align as u32).to_le_bytes());
+    fmt.extend(block_align.to_le_bytes());
+    fmt.extend(16u16.to_le_bytes());
+    fmt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smpl_round_trip() {
+        let fmt = pcm_fmt(2, 44100);
+        let data: Vec<u8> = (0..400).map(|i| i as u8).collect();
+        let file = write(&fmt, &data, Some((10, 60))).unwrap();
+
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.fmt, fmt);
+        assert_eq!(wav.data, data);
+        assert_eq!(wav.samples(), 100);
+        assert_eq!(wav.sample_loop(), Some((10, 60)));
+        // the end is stored inclusive
+        let smpl = wav.smpl.unwrap();
+        assert_eq!(read_u32(smpl, 36 + 12), Some(59));
+    }
+
+    #[test]
+    fn without_loop() {
+        let fmt = pcm_fmt(1, 22050);
+        // an odd length is padded
+        let file = write(&fmt, &[1, 2, 3], None).unwrap();
+        assert_eq!(file.len() % 2, 0);
+        assert_eq!(read_u32(&file, 4), Some(file.len() as u32 - 8));
+
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.data, [1, 2, 3]);
+        assert_eq!(wav.samples(), 1);
+        assert_eq!(wav.sample_loop(), None);
+    }
+
+    #[test]
+    fn empty_loop() {
+        assert_eq!(write(&pcm_fmt(1, 22050), &[0; 4], Some((0, 0))), None);
+    }
+}
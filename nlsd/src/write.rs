@@ -0,0 +1,257 @@
+use std::io::{self, Write};
+
+use byteorder::{WriteBytesExt, LE};
+
+use crate::{
+    audio::{self, AudioInfo, ProbeError},
+    looped, ImportError,
+};
+
+/// Builds an NLSD file from WAV or Ogg Vorbis sections, taking the header
+/// fields from the sections themselves.
+pub struct NlsdWrite<W: Write> {
+    inner: W,
+    begin: Option<Vec<u8>>,
+    middle: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum WriteError {
+    #[error("No loop section was given")]
+    MissingMiddle,
+
+    #[error("The {0} section is not a WAV or Ogg Vorbis file")]
+    UnrecognizedFormat(&'static str),
+
+    #[error("The {0} section has an invalid {1}")]
+    InvalidHeader(&'static str, &'static str),
+
+    #[error("The sections differ in {0}")]
+    Mismatch(&'static str),
+
+    #[error("Unsupported value for {0}: {1}")]
+    UnsupportedValue(&'static str, String),
+
+    #[error("The sections are too large")]
+    TooLarge,
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+impl<W> NlsdWrite<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        NlsdWrite {
+            inner,
+            begin: None,
+            middle: None,
+            end: None,
+        }
+    }
+
+    /// Sets the intro, played once before the loop.
+    pub fn set_begin(&mut self, data: Vec<u8>) {
+        self.begin = Some(data);
+    }
+
+    /// Sets the loop section, which every NLSD must have.
+    pub fn set_middle(&mut self, data: Vec<u8>) {
+        self.middle = Some(data);
+    }
+
+    /// Sets the outro, played once after the loop.
+    pub fn set_end(&mut self, data: Vec<u8>) {
+        self.end = Some(data);
+    }
+
+    /// Sets all the sections from a WAV or Ogg Vorbis file with the loop
+    /// marked, as written by [`NlsdRead::export`](crate::NlsdRead::export).
+    /// A file without a loop marked becomes a single looping section.
+    pub fn set_looped(&mut self, data: &[u8]) -> Result<(), ImportError> {
+        let [begin, middle, end] = looped::import(data)?;
+        self.begin = begin;
+        self.middle = middle;
+        self.end = end;
+        Ok(())
+    }
+
+    /// Writes the header and sections out and returns the inner writer.
+    pub fn finish(mut self) -> Result<W, WriteError> {
+        let middle = self.middle.as_deref().ok_or(WriteError::MissingMiddle)?;
+        let sections = [
+            ("intro", self.begin.as_deref()),
+            ("loop", Some(middle)),
+            ("outro", self.end.as_deref()),
+        ];
+
+        let mut info: Option<AudioInfo> = None;
+        let mut samples = 0u64;
+        for (name, data) in sections {
+            let Some(data) = data else {
+                continue;
+            };
+            let section_info = audio::probe(data).map_err(|e| match e {
+                ProbeError::UnrecognizedFormat => WriteError::UnrecognizedFormat(name),
+                ProbeError::Invalid(field) => WriteError::InvalidHeader(name, field),
+            })?;
+            if let Some(info) = info {
+                if section_info.format != info.format {
+                    return Err(WriteError::Mismatch("format"));
+                }
+                if section_info.sample_rate != info.sample_rate {
+                    return Err(WriteError::Mismatch("sample rate"));
+                }
+                if section_info.channels != info.channels {
+                    return Err(WriteError::Mismatch("channel count"));
+                }
+            }
+            info = Some(section_info);
+            samples += section_info.samples;
+        }
+        let info = info.unwrap();
+
+        let sample_rate = u16::try_from(info.sample_rate).map_err(|_| {
+            WriteError::UnsupportedValue("sample rate", format!("{}", info.sample_rate))
+        })?;
+        let stereo = match info.channels {
+            1 => 0,
+            2 => 1,
+            channels => {
+                return Err(WriteError::UnsupportedValue(
+                    "channels",
+                    format!("{}", channels),
+                ))
+            }
+        };
+        let samples = u32::try_from(samples).map_err(|_| WriteError::TooLarge)?;
+
+        let len = |data: Option<&[u8]>| data.map_or(0, <[u8]>::len) as u64;
+        let middle_ofs = len(self.begin.as_deref());
+        let end_ofs = middle_ofs + len(Some(middle));
+        let total_size = end_ofs + len(self.end.as_deref());
+        let total_size = u32::try_from(total_size).map_err(|_| WriteError::TooLarge)?;
+
+        self.inner.write_u32::<LE>(info.format)?;
+        self.inner.write_u32::<LE>(total_size)?;
+        self.inner.write_u16::<LE>(sample_rate)?;
+        self.inner.write_u8(stereo)?;
+        self.inner.write_u8(0)?;
+        self.inner.write_u32::<LE>(samples)?;
+        self.inner.write_u32::<LE>(middle_ofs as u32)?;
+        self.inner.write_u32::<LE>(end_ofs as u32)?;
+        for (_, data) in sections {
+            if let Some(data) = data {
+                self.inner.write_all(data)?;
+            }
+        }
+
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+    use crate::{ogg, wav, NlsdRead, NlsdSectionRead};
+
+    fn wav(samples: usize, fill: u8) -> Vec<u8> {
+        wav::write(&wav::pcm_fmt(2, 22050), &vec![fill; samples * 4], None).unwrap()
+    }
+
+    /// Finishes `nlsd` and reopens it, along with the header's middle_ofs,
+    /// end_ofs and total_size.
+    fn reopen(nlsd: NlsdWrite<Vec<u8>>) -> (NlsdRead<Cursor<Vec<u8>>>, [u32; 3]) {
+        let bytes = nlsd.finish().unwrap();
+        let field = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let offsets = [field(0x10), field(0x14), field(0x4)];
+        (NlsdRead::open(Cursor::new(bytes)).unwrap(), offsets)
+    }
+
+    fn sections(nlsd: &mut NlsdRead<Cursor<Vec<u8>>>) -> [Option<Vec<u8>>; 3] {
+        let read = |section: Option<NlsdSectionRead<'_, _>>| {
+            section.map(|mut section| {
+                let mut data = Vec::new();
+                section.read_to_end(&mut data).unwrap();
+                data
+            })
+        };
+        [
+            read(nlsd.section_begin().unwrap()),
+            read(Some(nlsd.section_middle().unwrap())),
+            read(nlsd.section_end().unwrap()),
+        ]
+    }
+
+    #[test]
+    fn all_sections() {
+        let (begin, middle, end) = (wav(100, 1), wav(300, 2), wav(50, 3));
+        let mut nlsd = NlsdWrite::new(Vec::new());
+        nlsd.set_begin(begin.clone());
+        nlsd.set_middle(middle.clone());
+        nlsd.set_end(end.clone());
+
+        let (mut nlsd, offsets) = reopen(nlsd);
+        assert_eq!(nlsd.format(), 5);
+        assert_eq!(nlsd.sample_rate(), 22050);
+        assert!(nlsd.stereo());
+        assert_eq!(nlsd.samples(), 450);
+        let middle_ofs = begin.len() as u32;
+        let end_ofs = middle_ofs + middle.len() as u32;
+        let total_size = end_ofs + end.len() as u32;
+        assert_eq!(offsets, [middle_ofs, end_ofs, total_size]);
+        assert_eq!(sections(&mut nlsd), [Some(begin), Some(middle), Some(end)]);
+    }
+
+    #[test]
+    fn middle_only() {
+        let middle = ogg::test_stream(1, 44100, 1, &[], &[1000, 2000]);
+        let mut nlsd = NlsdWrite::new(Vec::new());
+        nlsd.set_middle(middle.clone());
+
+        let (mut nlsd, offsets) = reopen(nlsd);
+        assert_eq!(nlsd.format(), 7);
+        assert_eq!(nlsd.sample_rate(), 44100);
+        assert!(!nlsd.stereo());
+        assert_eq!(nlsd.samples(), 2000);
+        let len = middle.len() as u32;
+        assert_eq!(offsets, [0, len, len]);
+        assert_eq!(sections(&mut nlsd), [None, Some(middle), None]);
+    }
+
+    #[test]
+    fn begin_without_end() {
+        let (begin, middle) = (wav(100, 1), wav(300, 2));
+        let mut nlsd = NlsdWrite::new(Vec::new());
+        nlsd.set_begin(begin.clone());
+        nlsd.set_middle(middle.clone());
+
+        let (mut nlsd, offsets) = reopen(nlsd);
+        assert_eq!(nlsd.samples(), 400);
+        let middle_ofs = begin.len() as u32;
+        let end_ofs = middle_ofs + middle.len() as u32;
+        assert_eq!(offsets, [middle_ofs, end_ofs, end_ofs]);
+        // the original reader's check reports an empty outro
+        assert_eq!(
+            sections(&mut nlsd),
+            [Some(begin), Some(middle), Some(Vec::new())]
+        );
+    }
+
+    #[test]
+    fn mismatched_sections() {
+        let mut nlsd = NlsdWrite::new(Vec::new());
+        nlsd.set_begin(wav(100, 1));
+        nlsd.set_middle(wav::write(&wav::pcm_fmt(2, 44100), &[0; 400], None).unwrap());
+        assert!(matches!(
+            nlsd.finish(),
+            Err(WriteError::Mismatch("sample rate"))
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use std::io::{self, Read, Seek};
+
+use crate::{Metadata, Vfs, VfsFile};
+
+/// DSARC FL archives are flat, so paths are file names.
+impl<R: Read + Seek> Vfs for makaikit_dsarcfl::Archive<R> {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>> {
+        match self.open_by_name(path) {
+            None => Err(crate::not_found(path)),
+            Some(file) => Ok(Box::new(file.map_err(io::Error::other)?)),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let index = self.find(path).ok_or_else(|| crate::not_found(path))?;
+        Ok(Metadata::new(self.entry(index).unwrap().size(), None))
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let mut paths: Vec<String> = self
+            .entries()
+            .filter_map(|entry| entry.name().ok()?.to_str().ok())
+            .filter(|name| crate::is_in_dir(name, dir))
+            .map(str::to_owned)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+}
+
+/// Paths are looked up as in [`makaikit_fafullfs::Archive::find`].
+impl<R: Read + Seek> Vfs for makaikit_fafullfs::Archive<R> {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>> {
+        let index = self.find(path).ok_or_else(|| crate::not_found(path))?;
+        let file = self.get_file(index).unwrap().map_err(io::Error::other)?;
+        Ok(Box::new(file))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let index = self.find(path).ok_or_else(|| crate::not_found(path))?;
+        // the format of the timestamps is unknown
+        Ok(Metadata::new(self.entry(index).unwrap().size(), None))
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let dir = dir.to_ascii_lowercase().replace('\\', "/");
+        let mut paths: Vec<String> = self
+            .entries()
+            .filter_map(|entry| entry.path().ok()?.to_str().ok())
+            .map(|path| path.replace('\\', "/"))
+            .filter(|path| crate::is_in_dir(&path.to_ascii_lowercase(), &dir))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+    io::{self, Read, Seek},
+};
+
+/// A difference in an entry between two archives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryDiff {
+    Added {
+        path: CString,
+        index: usize,
+    },
+    Removed {
+        path: CString,
+        index: usize,
+    },
+    Modified {
+        path: CString,
+        old_index: usize,
+        new_index: usize,
+    },
+}
+
+impl EntryDiff {
+    pub fn path(&self) -> &CStr {
+        match self {
+            EntryDiff::Added { path, .. }
+            | EntryDiff::Removed { path, .. }
+            | EntryDiff::Modified { path, .. } => path,
+        }
+    }
+}
+
+/// An archive whose entries can be compared with [`diff`].
+pub trait DiffArchive {
+    type Error: From<io::Error>;
+
+    /// The path of every entry, in archive order.
+    fn paths(&self) -> impl Iterator<Item = Result<&CStr, Self::Error>>;
+
+    fn size(&self, index: usize) -> u64;
+
+    /// The checksum stored for the entry, or 0 if it has none.
+    fn checksum(&self, index: usize) -> u64 {
+        let _ = index;
+        0
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<impl Read + '_, Self::Error>;
+}
+
+impl<R: Read + Seek> DiffArchive for makaikit_dsarcfl::Archive<R> {
+    type Error = makaikit_dsarcfl::ReadError;
+
+    fn paths(&self) -> impl Iterator<Item = Result<&CStr, Self::Error>> {
+        self.entries().map(|entry| entry.name())
+    }
+
+    fn size(&self, index: usize) -> u64 {
+        self.entry(index).unwrap().size()
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<impl Read + '_, Self::Error> {
+        self.get_file(index).unwrap()
+    }
+}
+
+impl<R: Read + Seek> DiffArchive for makaikit_fafullfs::Archive<R> {
+    type Error = makaikit_fafullfs::Error;
+
+    fn paths(&self) -> impl Iterator<Item = Result<&CStr, Self::Error>> {
+        self.entries().map(|entry| entry.path())
+    }
+
+    fn size(&self, index: usize) -> u64 {
+        self.entry(index).unwrap().size()
+    }
+
+    fn checksum(&self, index: usize) -> u64 {
+        self.entry(index).unwrap().checksum()
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<impl Read + '_, Self::Error> {
+        self.get_file(index).unwrap()
+    }
+}
+
+fn same_contents<A: Read, B: Read>(mut a: A, mut b: B) -> io::Result<bool> {
+    let mut buf_a = vec![0u8; 0x10000];
+    let mut buf_b = vec![0u8; 0x10000];
+    loop {
+        let len = a.read(&mut buf_a)?;
+        if len == 0 {
+            return Ok(b.read(&mut buf_b[..1])? == 0);
+        }
+        match b.read_exact(&mut buf_b[..len]) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            r => r?,
+        }
+        if buf_a[..len] != buf_b[..len] {
+            return Ok(false);
+        }
+    }
+}
+
+/// The index of the first entry with each path.
+fn paths<A: DiffArchive>(archive: &A) -> Result<BTreeMap<CString, usize>, A::Error> {
+    let mut paths = BTreeMap::new();
+    for (index, path) in archive.paths().enumerate() {
+        paths.entry(path?.to_owned()).or_insert(index);
+    }
+    Ok(paths)
+}
+
+/// Lists the entries added, removed and modified from `old` to `new`,
+/// ordered by path.
+///
+/// Entries are matched by exact path. Matching entries are modified if their
+/// sizes differ, or their checksums if both have one, or else their
+/// contents.
+pub fn diff<A, B>(old: &mut A, new: &mut B) -> Result<Vec<EntryDiff>, A::Error>
+where
+    A: DiffArchive,
+    B: DiffArchive<Error = A::Error>,
+{
+    let old_paths = paths(old)?;
+    let new_paths = paths(new)?;
+
+    let mut diffs = Vec::new();
+    for (path, &old_index) in old_paths.iter() {
+        let Some(&new_index) = new_paths.get(path) else {
+            diffs.push(EntryDiff::Removed {
+                path: path.clone(),
+                index: old_index,
+            });
+            continue;
+        };
+
+        let old_checksum = old.checksum(old_index);
+        let new_checksum = new.checksum(new_index);
+        let modified = if old.size(old_index) != new.size(new_index) {
+            true
+        } else if old_checksum != 0 && new_checksum != 0 {
+            old_checksum != new_checksum
+        } else {
+            let old_file = old.open_entry(old_index)?;
+            let new_file = new.open_entry(new_index)?;
+            !same_contents(old_file, new_file)?
+        };
+        if modified {
+            diffs.push(EntryDiff::Modified {
+                path: path.clone(),
+                old_index,
+                new_index,
+            });
+        }
+    }
+    for (path, &index) in new_paths.iter() {
+        if !old_paths.contains_key(path) {
+            diffs.push(EntryDiff::Added {
+                path: path.clone(),
+                index,
+            });
+        }
+    }
+
+    diffs.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use makaikit_fafullfs::{Archive, ArchiveWriter};
+
+    use super::*;
+
+    fn archive(files: &[(&CStr, &[u8], u64)]) -> Archive<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for &(path, contents, checksum) in files {
+            let mut file = writer.file(path).unwrap();
+            file.set_checksum(checksum);
+            file.write_all(contents).unwrap();
+        }
+        Archive::from_bytes(writer.finish().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn checksums_compared_only_when_both_present() {
+        let mut old = archive(&[
+            (c"a", b"same", 0),
+            (c"b", b"same", 1),
+            (c"c", b"old!", 1),
+            (c"d", b"same", 1),
+            (c"removed", b"", 0),
+        ]);
+        let mut new = archive(&[
+            (c"a", b"same", 2),
+            (c"b", b"same", 0),
+            (c"c", b"new!", 1),
+            (c"d", b"same", 2),
+            (c"added", b"", 0),
+        ]);
+
+        let diffs = diff(&mut old, &mut new).unwrap();
+        assert_eq!(
+            diffs,
+            vec![
+                EntryDiff::Added {
+                    path: c"added".to_owned(),
+                    index: 4
+                },
+                EntryDiff::Modified {
+                    path: c"d".to_owned(),
+                    old_index: 3,
+                    new_index: 3
+                },
+                EntryDiff::Removed {
+                    path: c"removed".to_owned(),
+                    index: 4
+                },
+            ]
+        );
+    }
+}
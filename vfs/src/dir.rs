@@ -0,0 +1,91 @@
+use std::{
+    fs::File,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::{Metadata, Vfs, VfsFile};
+
+/// Loose files in a directory on disk.
+pub struct DirVfs {
+    root: PathBuf,
+}
+
+impl DirVfs {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirVfs { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Joins `path` onto the root, refusing paths that could leave it.
+    fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let path = Path::new(path);
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} leaves the directory", path.display()),
+            ));
+        }
+        Ok(self.root.join(path))
+    }
+}
+
+impl Vfs for DirVfs {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>> {
+        Ok(Box::new(File::open(self.resolve(path)?)?))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_ok_and(|p| p.is_file())
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let metadata = std::fs::metadata(self.resolve(path)?)?;
+        if !metadata.is_file() {
+            return Err(crate::not_found(path));
+        }
+        Ok(Metadata::new(metadata.len(), metadata.modified().ok()))
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let dir_path = self.resolve(dir)?;
+        if !dir_path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths = Vec::new();
+        for entry in WalkDir::new(&dir_path) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("Unable to access file in {}: {}", dir_path.display(), e);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(&self.root).unwrap();
+            let path = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            paths.push(path);
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn host_path(&self, path: &str) -> Option<PathBuf> {
+        self.resolve(path).ok().filter(|p| p.is_file())
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Read, Seek},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+mod archive;
+mod diff;
+mod dir;
+mod overlay;
+
+pub use self::diff::{diff, DiffArchive, EntryDiff};
+pub use self::dir::DirVfs;
+pub use self::overlay::OverlayVfs;
+
+/// A file opened from a [`Vfs`].
+pub trait VfsFile: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> VfsFile for T {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn new(len: u64, modified: Option<SystemTime>) -> Self {
+        Metadata { len, modified }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+/// A read-only tree of files, addressed by relative paths separated with
+/// `/`, the way the games name them.
+pub trait Vfs {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>>;
+
+    /// Whether there is a file, not a directory, at `path`.
+    fn exists(&self, path: &str) -> bool;
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata>;
+
+    /// Lists the paths of the files in `dir` and its subdirectories, sorted.
+    /// An empty `dir` lists every file.
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+
+    /// Where the file at `path` can be found on disk as a loose file, if it
+    /// can be.
+    fn host_path(&self, path: &str) -> Option<PathBuf> {
+        let _ = path;
+        None
+    }
+}
+
+impl<V: Vfs + ?Sized> Vfs for Box<V> {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>> {
+        (**self).open(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        (**self).exists(path)
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        (**self).metadata(path)
+    }
+
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        (**self).list(dir)
+    }
+
+    fn host_path(&self, path: &str) -> Option<PathBuf> {
+        (**self).host_path(path)
+    }
+}
+
+pub(crate) fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{path} not found"))
+}
+
+/// Whether `path` is `dir` itself or lies beneath it.
+pub(crate) fn is_in_dir(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    dir.is_empty()
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
@@ -0,0 +1,165 @@
+use std::{io, path::PathBuf};
+
+use crate::{Metadata, Vfs, VfsFile};
+
+/// Stacks other filesystems, looking each path up in the first layer that
+/// has it.
+#[derive(Default)]
+pub struct OverlayVfs<'a> {
+    layers: Vec<Box<dyn Vfs + Send + Sync + 'a>>,
+}
+
+impl<'a> OverlayVfs<'a> {
+    pub fn new() -> Self {
+        OverlayVfs { layers: Vec::new() }
+    }
+
+    /// Adds a layer below the existing ones.
+    pub fn push<V: Vfs + Send + Sync + 'a>(&mut self, layer: V) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// The layers, from the first to the last pushed.
+    pub fn layers_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut (dyn Vfs + Send + Sync + 'a)> {
+        self.layers.iter_mut().map(|layer| &mut **layer)
+    }
+
+    fn layer_index(&self, path: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.exists(path))
+    }
+}
+
+impl<'a> Vfs for OverlayVfs<'a> {
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn VfsFile + '_>> {
+        let index = self
+            .layer_index(path)
+            .ok_or_else(|| crate::not_found(path))?;
+        self.layers[index].open(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.layer_index(path).is_some()
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let index = self
+            .layer_index(path)
+            .ok_or_else(|| crate::not_found(path))?;
+        self.layers[index].metadata(path)
+    }
+
+    /// A path an earlier layer also has is looked up there, so it is only
+    /// listed as that layer spells it. Whether paths that differ in case are
+    /// the same is up to each layer.
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            for path in layer.list(dir)? {
+                if !self.layers[..index].iter().any(|above| above.exists(&path)) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        Ok(paths)
+    }
+
+    /// The host path of the file in the first layer that has it, or `None`
+    /// if that layer is not on disk.
+    fn host_path(&self, path: &str) -> Option<PathBuf> {
+        self.layers[self.layer_index(path)?].host_path(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use makaikit_fafullfs::{Archive, ArchiveWriter};
+
+    use super::*;
+
+    fn archive(files: &[(&std::ffi::CStr, &[u8])]) -> Archive<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for &(path, contents) in files {
+            writer.file(path).unwrap().write_all(contents).unwrap();
+        }
+        Archive::from_bytes(writer.finish().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn list_matches_lookups() {
+        let mut overlay = OverlayVfs::new();
+        overlay.push(archive(&[(c"data/Script.lua", b"first")]));
+        overlay.push(archive(&[
+            (c"data/script.lua", b"second"),
+            (c"data/other.lua", b"second"),
+        ]));
+
+        let paths = overlay.list("").unwrap();
+        assert_eq!(paths, vec!["data/Script.lua", "data/other.lua"]);
+        for path in paths.iter() {
+            let mut contents = Vec::new();
+            overlay
+                .open(path)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            let expected: &[u8] = if path == "data/Script.lua" {
+                b"first"
+            } else {
+                b"second"
+            };
+            assert_eq!(contents, expected);
+        }
+        assert!(overlay.exists("DATA/SCRIPT.LUA"));
+    }
+
+    #[test]
+    fn list_keeps_case_sensitive_names() {
+        let dir = std::env::temp_dir().join(format!("makaikit-vfs-overlay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.lub"), b"dir").unwrap();
+
+        let mut writer = makaikit_dsarcfl::ArchiveWriter::new();
+        for (name, contents) in [
+            (c"a.lub", &b"lower"[..]),
+            (c"A.lub", b"upper"),
+            (c"b.lub", b"arc"),
+        ] {
+            writer.file(name).unwrap().write_all(contents).unwrap();
+        }
+        let dsarc = makaikit_dsarcfl::Archive::open(Cursor::new(writer.finish().unwrap())).unwrap();
+
+        let mut overlay = OverlayVfs::new();
+        overlay.push(crate::DirVfs::new(&dir));
+        overlay.push(dsarc);
+
+        let paths = overlay.list("").unwrap();
+        let mut contents = Vec::new();
+        for path in paths.iter() {
+            let mut file_contents = Vec::new();
+            overlay
+                .open(path)
+                .unwrap()
+                .read_to_end(&mut file_contents)
+                .unwrap();
+            contents.push(file_contents);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths, vec!["A.lub", "a.lub", "b.lub"]);
+        assert_eq!(contents, [&b"upper"[..], b"lower", b"dir"]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    hint::black_box,
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use makaikit_ykcmp::{compress_with, decompress_to_vec, Decoder, Encoding};
+
+/// Somewhat compressible input resembling a model or texture blob: runs of
+/// noise interleaved with copies of earlier data.
+fn sample_data(len: usize) -> Vec<u8> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let r = next();
+        if data.len() > 16 && r % 2 == 0 {
+            let back = 1 + (r >> 8) as usize % data.len().min(4096);
+            let count = 3 + (r >> 24) as usize % 64;
+            for _ in 0..count.min(len - data.len()) {
+                data.push(data[data.len() - back]);
+            }
+        } else {
+            data.push((r >> 32) as u8 & 0x3F);
+        }
+    }
+    data
+}
+
+fn bench(name: &str, bytes: usize, mut f: impl FnMut()) {
+    f();
+    let mut iters = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        f();
+        iters += 1;
+    }
+    let per_iter = start.elapsed() / iters;
+    let throughput = bytes as f64 / per_iter.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{name:<32} {per_iter:>12.2?}/iter {throughput:>10.1} MiB/s");
+}
+
+fn main() {
+    for len in [64 * 1024, 4 * 1024 * 1024] {
+        let data = sample_data(len);
+        for encoding in [Encoding::NisLz77, Encoding::Lz4V8] {
+            let compressed = compress_with(&data, encoding).unwrap();
+            assert_eq!(decompress_to_vec(&compressed).unwrap(), data);
+
+            bench(&format!("{encoding:?} {len} Decoder"), len, || {
+                let mut out = Vec::with_capacity(len);
+                Decoder::new(black_box(&compressed[..]))
+                    .unwrap()
+                    .read_to_end(&mut out)
+                    .unwrap();
+                black_box(out);
+            });
+            bench(
+                &format!("{encoding:?} {len} decompress_to_vec"),
+                len,
+                || {
+                    black_box(decompress_to_vec(black_box(&compressed[..])).unwrap());
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::{self, Write};
+
+use crate::Encoding;
+
+mod nislz77;
+
+const HEADER_LEN: usize = 0x14;
+
+/// Buffers everything written to it and emits a complete YKCMP file to
+/// `inner` on [`Encoder::finish`].
+pub struct Encoder<W: Write> {
+    inner: W,
+    encoding: Encoding,
+    level: CompressionLevel,
+    buf: Vec<u8>,
+}
+
+/// How hard the NIS LZ77 encoder searches for a small encoding. LZ4 output
+/// is the same at every level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CompressionLevel {
+    Fast,
+    #[default]
+    Default,
+    /// Optimal parsing over every literal run and copy length the format
+    /// allows; much slower, but never larger than the other levels.
+    Best,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeError {
+    #[error("Input is too large to be described by a YKCMP header")]
+    TooLarge,
+
+    #[error("Compressed size {len} is over the budget of {budget} bytes")]
+    OverBudget { len: usize, budget: usize },
+
+    #[error("IO error")]
+    Io(#[from] io::Error),
+}
+
+impl<W> Encoder<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self::with_encoding(inner, Encoding::NisLz77)
+    }
+
+    pub fn with_encoding(inner: W, encoding: Encoding) -> Self {
+        Encoder {
+            inner,
+            encoding,
+            level: CompressionLevel::default(),
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn set_level(&mut self, level: CompressionLevel) {
+        self.level = level;
+    }
+
+    /// Compresses the buffered input, writes it out and returns the inner
+    /// writer.
+    pub fn finish(mut self) -> Result<W, EncodeError> {
+        let out = encode(&self.buf, self.encoding, self.level)?;
+        self.inner.write_all(&out[..])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W> Write for Encoder<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // nothing is written to inner until finish
+        Ok(())
+    }
+}
+
+/// Compresses `data` into a complete YKCMP file using NIS LZ77.
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    compress_with(data, Encoding::NisLz77)
+}
+
+/// Compresses `data` into a complete YKCMP file using `encoding`.
+pub fn compress_with(data: &[u8], encoding: Encoding) -> Result<Vec<u8>, EncodeError> {
+    compress_with_level(data, encoding, CompressionLevel::default())
+}
+
+/// Compresses `data` into a complete YKCMP file using `encoding` at `level`.
+pub fn compress_with_level(
+    data: &[u8],
+    encoding: Encoding,
+    level: CompressionLevel,
+) -> Result<Vec<u8>, EncodeError> {
+    encode(data, encoding, level)
+}
+
+/// Compresses `data` into a complete YKCMP file no larger than `budget`
+/// bytes, usually the size of the file being replaced. Falls back to
+/// [`CompressionLevel::Best`] if the default level doesn't fit, and reports
+/// the smallest size reached through [`EncodeError::OverBudget`] if that
+/// doesn't fit either.
+pub fn compress_to_budget(
+    data: &[u8],
+    encoding: Encoding,
+    budget: usize,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut out = encode(data, encoding, CompressionLevel::Default)?;
+    if out.len() > budget && encoding == Encoding::NisLz77 {
+        let best = encode(data, encoding, CompressionLevel::Best)?;
+        if best.len() < out.len() {
+            out = best;
+        }
+    }
+    if out.len() > budget {
+        return Err(EncodeError::OverBudget {
+            len: out.len(),
+            budget,
+        });
+    }
+    Ok(out)
+}
+
+fn encode(
+    data: &[u8],
+    encoding: Encoding,
+    level: CompressionLevel,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut out = Vec::with_capacity(HEADER_LEN + data.len() + data.len() / 0x7F + 1);
+    out.extend_from_slice(&[0; HEADER_LEN]);
+    match encoding {
+        Encoding::NisLz77 => nislz77::compress(data, &mut out, level),
+        Encoding::Lz4V8 | Encoding::Lz4V9 => {
+            out.extend_from_slice(&lz4_flex::block::compress(data)[..]);
+        }
+    }
+
+    // the compressed length includes the header
+    let comp_len = u32::try_from(out.len()).map_err(|_| EncodeError::TooLarge)?;
+    let decomp_len = u32::try_from(data.len()).map_err(|_| EncodeError::TooLarge)?;
+
+    out[0..8].copy_from_slice(b"YKCMP_V1");
+    out[8..12].copy_from_slice(&encoding.id().to_le_bytes());
+    out[12..16].copy_from_slice(&comp_len.to_le_bytes());
+    out[16..20].copy_from_slice(&decomp_len.to_le_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text whose greedy parse the optimal parse beats.
+    fn text() -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in 0..400u32 {
+            text.extend(format!("item {} costs {} hl, ", i * 7 % 31, i * i % 997).bytes());
+        }
+        text
+    }
+
+    fn level_len(data: &[u8], level: CompressionLevel) -> usize {
+        compress_with_level(data, Encoding::NisLz77, level)
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn best_is_smallest() {
+        let inputs = [
+            text(),
+            vec![0; 5000],
+            b"abcabdabcabdabcabe".repeat(100),
+            (0..3000u32).map(|i| (i * i % 251) as u8).collect(),
+        ];
+        for input in inputs.iter() {
+            let best = level_len(input, CompressionLevel::Best);
+            assert!(best <= level_len(input, CompressionLevel::Default));
+            assert!(best <= level_len(input, CompressionLevel::Fast));
+        }
+    }
+
+    #[test]
+    fn budget_met_at_default_level() {
+        let data = text();
+        let default = compress_with(&data, Encoding::NisLz77).unwrap();
+        let out = compress_to_budget(&data, Encoding::NisLz77, default.len()).unwrap();
+        assert_eq!(out, default);
+    }
+
+    #[test]
+    fn budget_met_at_best_level() {
+        let data = text();
+        let default = level_len(&data, CompressionLevel::Default);
+        let best = level_len(&data, CompressionLevel::Best);
+        assert!(best < default);
+        let out = compress_to_budget(&data, Encoding::NisLz77, best).unwrap();
+        assert_eq!(out.len(), best);
+        assert_eq!(crate::decompress_to_vec(&out).unwrap(), data);
+    }
+
+    #[test]
+    fn over_budget() {
+        let data = text();
+        let best = level_len(&data, CompressionLevel::Best);
+        match compress_to_budget(&data, Encoding::NisLz77, best - 1) {
+            Err(EncodeError::OverBudget { len, budget }) => {
+                assert_eq!(len, best);
+                assert_eq!(budget, best - 1);
+            }
+            r => panic!("expected OverBudget, got {:?}", r.map(|out| out.len())),
+        }
+
+        let lz4 = compress_with(&data, Encoding::Lz4V8).unwrap();
+        assert!(matches!(
+            compress_to_budget(&data, Encoding::Lz4V8, lz4.len() - 1),
+            Err(EncodeError::OverBudget { .. })
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+
+use super::CompressionLevel;
+
+const WINDOW: usize = 4096;
+const MAX_MATCH: usize = 514;
+const MAX_LITERAL: usize = 0x7F;
+const NIL: u32 = u32::MAX;
+
+/// Hash chains keyed on the next two bytes, so that the one and two byte
+/// pointer forms can still find their short matches.
+struct MatchFinder {
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    max_chain: usize,
+}
+
+impl MatchFinder {
+    fn new(max_chain: usize) -> Self {
+        MatchFinder {
+            head: vec![NIL; 0x10000],
+            prev: vec![NIL; WINDOW],
+            max_chain,
+        }
+    }
+
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + 1 >= data.len() {
+            return;
+        }
+        let key = (data[pos] as usize) << 8 | data[pos + 1] as usize;
+        self.prev[pos % WINDOW] = self.head[key];
+        self.head[key] = pos as u32;
+    }
+
+    /// Calls `f` with the `(len, off)` of every earlier occurrence of the
+    /// bytes at `pos`, nearest first.
+    fn for_each_match(&self, data: &[u8], pos: usize, mut f: impl FnMut(usize, usize)) {
+        if pos + 1 >= data.len() {
+            return;
+        }
+        let key = (data[pos] as usize) << 8 | data[pos + 1] as usize;
+        let max_len = MAX_MATCH.min(data.len() - pos);
+
+        let mut candidate = self.head[key];
+        let mut depth = 0;
+
+        while candidate != NIL && depth < self.max_chain {
+            let cand = candidate as usize;
+            let off = pos - cand;
+            if off > WINDOW {
+                break;
+            }
+
+            let len = match_len(data, cand, pos, max_len);
+            f(len, off);
+            if len == max_len {
+                break;
+            }
+
+            let next = self.prev[cand % WINDOW];
+            if next == NIL || next >= candidate {
+                break;
+            }
+            candidate = next;
+            depth += 1;
+        }
+    }
+
+    /// Returns the `(len, off)` of the most profitable pointer at `pos`.
+    fn find(&self, data: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let mut best = None;
+        let mut best_score = (0, 0);
+
+        self.for_each_match(data, pos, |len, off| {
+            for len in [len.min(4), len.min(33), len] {
+                if let Some(cost) = ptr_cost(len, off) {
+                    // prefer the biggest saving, then the longest copy
+                    let score = (len as isize - cost as isize, len);
+                    if score.0 > 0 && score > best_score {
+                        best_score = score;
+                        best = Some((len, off));
+                    }
+                }
+            }
+        });
+
+        best
+    }
+}
+
+fn match_len(data: &[u8], cand: usize, pos: usize, max_len: usize) -> usize {
+    let mut len = 0;
+    while len < max_len && data[cand + len] == data[pos + len] {
+        len += 1;
+    }
+    len
+}
+
+/// The encoded size of the smallest pointer command that can express a copy
+/// of `len` bytes from `off` bytes back.
+fn ptr_cost(len: usize, off: usize) -> Option<usize> {
+    if off == 0 || off > WINDOW || len == 0 || len > MAX_MATCH {
+        None
+    } else if len <= 4 && off <= 16 {
+        Some(1)
+    } else if (2..=33).contains(&len) && off <= 256 {
+        Some(2)
+    } else if len >= 3 {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+fn write_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERAL) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn write_ptr(out: &mut Vec<u8>, len: usize, off: usize) {
+    let off = off - 1;
+    match ptr_cost(len, off + 1) {
+        Some(1) => {
+            out.push((((len + 7) << 4) | off) as u8);
+        }
+        Some(2) => {
+            out.push((len + 0xBE) as u8);
+            out.push(off as u8);
+        }
+        Some(3) => {
+            let len = len - 3;
+            out.push((0xE0 | (len >> 4)) as u8);
+            out.push((((len & 0x0F) << 4) | (off >> 8)) as u8);
+            out.push(off as u8);
+        }
+        _ => unreachable!("unencodable pointer len={len} off={}", off + 1),
+    }
+}
+
+/// Compresses `data` into a NIS LZ77 command stream appended to `out`.
+pub fn compress(data: &[u8], out: &mut Vec<u8>, level: CompressionLevel) {
+    match level {
+        CompressionLevel::Fast => compress_greedy(data, out, 16),
+        CompressionLevel::Default => compress_greedy(data, out, 256),
+        CompressionLevel::Best => compress_optimal(data, out),
+    }
+}
+
+fn compress_greedy(data: &[u8], out: &mut Vec<u8>, max_chain: usize) {
+    let mut finder = MatchFinder::new(max_chain);
+    let mut literal_start = 0;
+    let mut pos = 0;
+
+    while pos < data.len() {
+        match finder.find(data, pos) {
+            Some((len, off)) => {
+                write_literals(out, &data[literal_start..pos]);
+                write_ptr(out, len, off);
+                for p in pos..pos + len {
+                    finder.insert(data, p);
+                }
+                pos += len;
+                literal_start = pos;
+            }
+            None => {
+                finder.insert(data, pos);
+                pos += 1;
+            }
+        }
+    }
+    write_literals(out, &data[literal_start..]);
+}
+
+#[derive(Clone, Copy)]
+struct Step {
+    cost: u32,
+    /// Length of the command ending here; zero only at the start.
+    len: u32,
+    /// Pointer offset, or zero if the command is a literal run.
+    off: u32,
+}
+
+/// Finds the cheapest sequence of commands for the whole input by relaxing
+/// every literal run and the pointer lengths available at each position.
+fn compress_optimal(data: &[u8], out: &mut Vec<u8>) {
+    let n = data.len();
+    let mut steps = vec![
+        Step {
+            cost: u32::MAX,
+            len: 0,
+            off: 0,
+        };
+        n + 1
+    ];
+    steps[0].cost = 0;
+
+    let mut finder = MatchFinder::new(WINDOW);
+    // smallest offset that reaches each match length, nearest first
+    let mut stairs: Vec<(usize, usize)> = Vec::new();
+    // candidates for the start of a literal run ending at the current
+    // position, kept ordered by cost - position
+    let mut literal_starts = VecDeque::<usize>::new();
+
+    for pos in 0..=n {
+        // a literal run from `start` to `pos` costs its bytes plus one header
+        if pos > 0 {
+            let start = pos - 1;
+            let key = |i: usize| steps[i].cost as i64 - i as i64;
+            while literal_starts.back().is_some_and(|&i| key(i) >= key(start)) {
+                literal_starts.pop_back();
+            }
+            literal_starts.push_back(start);
+            while literal_starts
+                .front()
+                .is_some_and(|&i| pos - i > MAX_LITERAL)
+            {
+                literal_starts.pop_front();
+            }
+
+            let start = *literal_starts.front().unwrap();
+            let cost = steps[start].cost + 1 + (pos - start) as u32;
+            if cost < steps[pos].cost {
+                steps[pos] = Step {
+                    cost,
+                    len: (pos - start) as u32,
+                    off: 0,
+                };
+            }
+        }
+        if pos == n {
+            break;
+        }
+
+        stairs.clear();
+        // one byte copies only fit in the short form, which the two byte
+        // hash chains cannot see
+        if let Some(off) = (1..=16.min(pos)).find(|&off| data[pos - off] == data[pos]) {
+            stairs.push((1, off));
+        }
+        finder.for_each_match(data, pos, |len, off| {
+            if stairs.last().is_none_or(|&(best, _)| len > best) {
+                stairs.push((len, off));
+            }
+        });
+        finder.insert(data, pos);
+
+        let base = steps[pos].cost;
+        let mut relax = |len: usize, off: usize| {
+            if let Some(cost) = ptr_cost(len, off) {
+                let cost = base + cost as u32;
+                let next = &mut steps[pos + len];
+                if cost < next.cost {
+                    *next = Step {
+                        cost,
+                        len: len as u32,
+                        off: off as u32,
+                    };
+                }
+            }
+        };
+        // every length is tried, as stopping a copy short can line up a
+        // cheaper command after it
+        let mut len = 1;
+        for &(max_len, off) in stairs.iter() {
+            while len <= max_len {
+                relax(len, off);
+                len += 1;
+            }
+        }
+    }
+
+    let mut commands = Vec::new();
+    let mut pos = n;
+    while pos > 0 {
+        let step = steps[pos];
+        commands.push((pos, step));
+        pos -= step.len as usize;
+    }
+
+    let mut literal_start = 0;
+    for (end, step) in commands.into_iter().rev() {
+        let start = end - step.len as usize;
+        if step.off == 0 {
+            continue;
+        }
+        write_literals(out, &data[literal_start..start]);
+        write_ptr(out, step.len as usize, step.off as usize);
+        literal_start = end;
+    }
+    write_literals(out, &data[literal_start..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{compress_with_level, decompress_to_vec, Decoder, Encoding};
+
+    /// Wraps a command stream in a YKCMP header.
+    fn ykcmp(stream: &[u8], decomp_len: usize) -> Vec<u8> {
+        let mut out = b"YKCMP_V1".to_vec();
+        out.extend(4u32.to_le_bytes());
+        out.extend((0x14 + stream.len() as u32).to_le_bytes());
+        out.extend((decomp_len as u32).to_le_bytes());
+        out.extend(stream);
+        out
+    }
+
+    /// Decodes through both the in-memory and the streaming decoder, the
+    /// latter a byte at a time so that its window is as small as it gets.
+    fn decode_both(file: &[u8]) -> Vec<u8> {
+        let in_memory = decompress_to_vec(file).unwrap();
+        let mut decoder = Decoder::new(file).unwrap();
+        let mut streamed = Vec::new();
+        let mut byte = [0u8; 1];
+        while decoder.read(&mut byte).unwrap() == 1 {
+            streamed.push(byte[0]);
+        }
+        assert_eq!(in_memory, streamed);
+        in_memory
+    }
+
+    /// Deterministic noise that doesn't compress.
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pointer_boundaries() {
+        // (len, off, command size)
+        let cases = [
+            // short
+            (1, 1, 1),
+            (4, 1, 1),
+            (1, 16, 1),
+            (4, 16, 1),
+            // long
+            (2, 17, 2),
+            (5, 1, 2),
+            (33, 1, 2),
+            (2, 256, 2),
+            (33, 256, 2),
+            // longer
+            (34, 1, 3),
+            (3, 257, 3),
+            (514, 257, 3),
+            (3, 4096, 3),
+            (514, 4096, 3),
+        ];
+        let prefix = noise(4096, 1);
+        for (len, off, size) in cases {
+            let mut stream = Vec::new();
+            write_literals(&mut stream, &prefix);
+            let literals_len = stream.len();
+            write_ptr(&mut stream, len, off);
+            assert_eq!(stream.len() - literals_len, size, "len={len} off={off}");
+
+            let mut expected = prefix.clone();
+            for _ in 0..len {
+                expected.push(expected[expected.len() - off]);
+            }
+            let decoded = decode_both(&ykcmp(&stream, expected.len()));
+            assert!(decoded == expected, "len={len} off={off}");
+        }
+    }
+
+    #[test]
+    fn unencodable_pointers() {
+        assert_eq!(ptr_cost(0, 1), None);
+        assert_eq!(ptr_cost(1, 17), None);
+        assert_eq!(ptr_cost(2, 257), None);
+        assert_eq!(ptr_cost(515, 1), None);
+        assert_eq!(ptr_cost(3, 4097), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut periodic = noise(4096, 2);
+        periodic.extend_from_within(..);
+        periodic.extend(noise(4097, 3));
+        periodic.extend_from_within(8192..);
+        let inputs = [
+            Vec::new(),
+            vec![0x42],
+            vec![0; 10_000],
+            noise(20_000, 4),
+            b"the quick brown fox jumps over the lazy dog ".repeat(300),
+            periodic,
+            (0..=255u8).cycle().take(70_000).collect(),
+        ];
+        for input in inputs.iter() {
+            for level in [
+                CompressionLevel::Fast,
+                CompressionLevel::Default,
+                CompressionLevel::Best,
+            ] {
+                let file = compress_with_level(input, Encoding::NisLz77, level).unwrap();
+                assert!(decode_both(&file) == *input, "{level:?}");
+            }
+        }
+    }
+}
@@ -1,12 +1,22 @@
-use std::io::{self, Cursor, Read};
+use std::io::{self, Cursor, Read, Take};
+
+use crate::Encoding;
 
 use self::nislz77::NisLz77Reader;
 
 mod nislz77;
 
+const HEADER_LEN: u64 = 0x14;
+
+/// Neither encoding can expand a byte of payload into more than this many
+/// bytes, which bounds how much a header can make a decoder allocate.
+const MAX_RATIO: u64 = 256;
+
 pub struct Decoder<R: Read> {
     inner: DecoderInner<R>,
-    _decomp_len: u64,
+    encoding: Encoding,
+    decomp_len: u64,
+    pos: u64,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -17,6 +27,32 @@ pub enum DecodeError {
     #[error("Unsupported encoding variant {0}")]
     UnsupportedEncoding(u32),
 
+    #[error("Header declares a compressed size of {0} bytes, less than the header itself")]
+    InvalidCompressedLen(u64),
+
+    #[error("Header declares {decomp_len} bytes, more than {comp_len} compressed bytes can hold")]
+    ImplausibleLen { comp_len: u64, decomp_len: u64 },
+
+    /// If the data decompresses to more than the header declares, `actual`
+    /// is how much it needed by the point it stopped.
+    #[error("Decompressed {actual} bytes but the header declares {expected}")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[error("Compressed data ended before the declared size was reached")]
+    Truncated,
+
+    #[error("Compressed data continues past the declared size")]
+    TrailingData,
+
+    #[error("Decompressed data runs past the declared size of {0} bytes")]
+    OutputOverflow(u64),
+
+    #[error("Pointer copy reaches before the start of the output")]
+    InvalidPointer,
+
+    #[error("Output buffer is smaller than the declared size of {0} bytes")]
+    OutputTooSmall(u64),
+
     #[error("IO error")]
     Io(#[from] io::Error),
 
@@ -25,48 +61,169 @@ pub enum DecodeError {
 }
 
 enum DecoderInner<R: Read> {
-    Lz77(NisLz77Reader<R>),
+    Lz77(NisLz77Reader<Take<R>>),
     Lz4(Cursor<Vec<u8>>),
 }
 
+struct Header {
+    encoding: Encoding,
+    comp_len: u64,
+    decomp_len: u64,
+}
+
+impl Header {
+    fn parse(r: &[u8]) -> Result<Self, DecodeError> {
+        if r.len() < 8 {
+            return Err(DecodeError::Truncated);
+        }
+        if &r[..8] != b"YKCMP_V1" {
+            return Err(DecodeError::InvalidMagic);
+        }
+        if r.len() < HEADER_LEN as usize {
+            return Err(DecodeError::Truncated);
+        }
+
+        let encoding = u32::from_le_bytes(r[8..12].try_into().unwrap());
+        let comp_len = u32::from_le_bytes(r[12..16].try_into().unwrap()) as u64;
+        let decomp_len = u32::from_le_bytes(r[16..20].try_into().unwrap()) as u64;
+
+        // the compressed length includes the header
+        if comp_len < HEADER_LEN {
+            return Err(DecodeError::InvalidCompressedLen(comp_len));
+        }
+        if decomp_len > (comp_len - HEADER_LEN) * MAX_RATIO {
+            return Err(DecodeError::ImplausibleLen {
+                comp_len,
+                decomp_len,
+            });
+        }
+
+        Ok(Header {
+            encoding: Encoding::from_id(encoding)
+                .ok_or(DecodeError::UnsupportedEncoding(encoding))?,
+            comp_len,
+            decomp_len,
+        })
+    }
+
+    fn payload_len(&self) -> u64 {
+        self.comp_len - HEADER_LEN
+    }
+}
+
+fn decompress_lz4(compressed: &[u8], decompressed: &mut [u8]) -> Result<(), DecodeError> {
+    let actual = match lz4_flex::block::decompress_into(compressed, decompressed) {
+        Err(lz4_flex::block::DecompressError::OutputTooSmall { expected, .. }) => {
+            return Err(DecodeError::SizeMismatch {
+                expected: decompressed.len() as u64,
+                actual: expected as u64,
+            });
+        }
+        r => r?,
+    };
+    if actual != decompressed.len() {
+        return Err(DecodeError::SizeMismatch {
+            expected: decompressed.len() as u64,
+            actual: actual as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Decompresses a complete in-memory YKCMP file.
+pub fn decompress_to_vec(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let header = Header::parse(data)?;
+    if header.comp_len > data.len() as u64 {
+        return Err(DecodeError::Truncated);
+    }
+    let mut out = vec![0u8; header.decomp_len as usize];
+    decompress_into(data, &mut out[..])?;
+    Ok(out)
+}
+
+/// Decompresses a complete in-memory YKCMP file into the start of `out`,
+/// returning the number of bytes written.
+pub fn decompress_into(data: &[u8], out: &mut [u8]) -> Result<usize, DecodeError> {
+    let header = Header::parse(data)?;
+    let decomp_len = header.decomp_len as usize;
+    let out = out
+        .get_mut(..decomp_len)
+        .ok_or(DecodeError::OutputTooSmall(header.decomp_len))?;
+    let payload = data
+        .get(HEADER_LEN as usize..header.comp_len as usize)
+        .ok_or(DecodeError::Truncated)?;
+
+    match header.encoding {
+        Encoding::NisLz77 => nislz77::decompress_into(payload, out)?,
+        Encoding::Lz4V8 | Encoding::Lz4V9 => decompress_lz4(payload, out)?,
+    }
+    Ok(decomp_len)
+}
+
+fn read_exact_or_truncated<R: Read>(inner: &mut R, buf: &mut [u8]) -> Result<(), DecodeError> {
+    match inner.read_exact(buf) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(DecodeError::Truncated),
+        r => Ok(r?),
+    }
+}
+
 impl<R> Decoder<R>
 where
     R: Read,
 {
     pub fn new(mut inner: R) -> Result<Self, DecodeError> {
-        let mut r = [0u8; 12];
+        let mut r = [0u8; HEADER_LEN as usize];
 
-        inner.read_exact(&mut r[..8])?;
+        read_exact_or_truncated(&mut inner, &mut r[..8])?;
         if &r[..8] != b"YKCMP_V1" {
             return Err(DecodeError::InvalidMagic);
         }
 
-        inner.read_exact(&mut r[..12])?;
-        let encoding = u32::from_le_bytes(unsafe { *(&r[0..4] as *const [u8] as *const [u8; 4]) });
+        read_exact_or_truncated(&mut inner, &mut r[8..])?;
+        let header = Header::parse(&r)?;
 
-        let comp_len =
-            u32::from_le_bytes(unsafe { *(&r[4..8] as *const [u8] as *const [u8; 4]) }) as u64;
-        let decomp_len =
-            u32::from_le_bytes(unsafe { *(&r[8..12] as *const [u8] as *const [u8; 4]) }) as u64;
+        // stop at the end of the payload, whatever follows it
+        let mut payload = inner.take(header.payload_len());
+        let decoder_inner = match header.encoding {
+            Encoding::NisLz77 => DecoderInner::Lz77(NisLz77Reader::new(payload)),
+            Encoding::Lz4V8 | Encoding::Lz4V9 => {
+                // grown as it is read rather than trusting the header
+                let mut compressed = Vec::new();
+                payload.read_to_end(&mut compressed)?;
+                if (compressed.len() as u64) < header.payload_len() {
+                    return Err(DecodeError::Truncated);
+                }
 
-        let decoder_inner = match encoding {
-            4 => DecoderInner::Lz77(NisLz77Reader::new(inner)),
-            8 | 9 => {
-                let compressed = vec![0u8; comp_len as usize];
-                let decompressed = lz4_flex::decompress(&compressed[..], decomp_len as usize)?;
+                let mut decompressed = vec![0u8; header.decomp_len as usize];
+                decompress_lz4(&compressed, &mut decompressed)?;
 
                 DecoderInner::Lz4(Cursor::new(decompressed))
             }
-            e => {
-                return Err(DecodeError::UnsupportedEncoding(e));
-            }
         };
 
         Ok(Decoder {
             inner: decoder_inner,
-            _decomp_len: decomp_len,
+            encoding: header.encoding,
+            decomp_len: header.decomp_len,
+            pos: 0,
         })
     }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The decompressed size declared by the header.
+    pub fn decompressed_len(&self) -> u64 {
+        self.decomp_len
+    }
+
+    fn read_inner(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            DecoderInner::Lz77(ref mut lz77) => lz77.read(buf),
+            DecoderInner::Lz4(ref mut inner) => inner.read(buf),
+        }
+    }
 }
 
 impl<R> Read for Decoder<R>
@@ -74,9 +231,123 @@ impl<R> Read for Decoder<R>
     R: Read,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self.inner {
-            DecoderInner::Lz77(ref mut lz77) => lz77.read(buf),
-            DecoderInner::Lz4(ref mut inner) => inner.read(buf),
+        let remaining = self.decomp_len - self.pos;
+        if remaining == 0 {
+            if !buf.is_empty() && self.read_inner(&mut [0u8; 1])? != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    DecodeError::TrailingData,
+                ));
+            }
+            return Ok(0);
+        }
+
+        let len = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+        let bytes_read = self.read_inner(&mut buf[..len])?;
+        if bytes_read == 0 && len != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                DecodeError::Truncated,
+            ));
         }
+        self.pos += bytes_read as u64;
+
+        Ok(bytes_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress_with;
+
+    fn set_u32(file: &mut [u8], at: usize, value: u32) {
+        file[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn decode_streaming(file: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut out = Vec::new();
+        Decoder::new(file)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn compressed_len_below_header() {
+        let mut file = compress_with(b"hello", Encoding::Lz4V8).unwrap();
+        set_u32(&mut file, 12, 0x13);
+        assert!(matches!(
+            Decoder::new(&file[..]),
+            Err(DecodeError::InvalidCompressedLen(0x13))
+        ));
+        assert!(matches!(
+            decompress_to_vec(&file),
+            Err(DecodeError::InvalidCompressedLen(0x13))
+        ));
+    }
+
+    #[test]
+    fn implausible_decompressed_len() {
+        for encoding in [Encoding::NisLz77, Encoding::Lz4V8] {
+            let mut file = compress_with(b"hello", encoding).unwrap();
+            set_u32(&mut file, 16, u32::MAX);
+            assert!(matches!(
+                Decoder::new(&file[..]),
+                Err(DecodeError::ImplausibleLen { .. })
+            ));
+            assert!(matches!(
+                decompress_to_vec(&file),
+                Err(DecodeError::ImplausibleLen { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn compressed_len_past_end() {
+        for encoding in [Encoding::NisLz77, Encoding::Lz4V8] {
+            let mut file = compress_with(&[7; 1000], encoding).unwrap();
+            let comp_len = file.len() as u32;
+            set_u32(&mut file, 12, comp_len + 100);
+            assert!(matches!(
+                decompress_to_vec(&file),
+                Err(DecodeError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn padding_after_payload() {
+        let data = b"padding follows the payload in archives".repeat(20);
+        for encoding in [Encoding::NisLz77, Encoding::Lz4V8] {
+            let mut file = compress_with(&data, encoding).unwrap();
+            // not all zeroes, which would read as empty literal runs
+            file.extend([0x01, 0xAA, 0xFF, 0xFF]);
+            assert_eq!(decode_streaming(&file).unwrap(), data);
+            assert_eq!(decompress_to_vec(&file).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn lz4_larger_than_declared() {
+        let data = [3; 1000];
+        let mut file = compress_with(&data, Encoding::Lz4V8).unwrap();
+        set_u32(&mut file, 16, 500);
+        assert!(matches!(
+            decompress_to_vec(&file),
+            Err(DecodeError::SizeMismatch { expected: 500, .. })
+        ));
+        assert!(matches!(
+            Decoder::new(&file[..]),
+            Err(DecodeError::SizeMismatch { expected: 500, .. })
+        ));
+    }
+
+    #[test]
+    fn nislz77_larger_than_declared() {
+        let mut file = compress_with(&[3; 1000], Encoding::NisLz77).unwrap();
+        set_u32(&mut file, 16, 500);
+        assert!(matches!(
+            decompress_to_vec(&file),
+            Err(DecodeError::OutputOverflow(500))
+        ));
     }
 }
@@ -3,6 +3,8 @@
     io::{self, Cursor, Read, Write},
 };
 
+use super::DecodeError;
+
 pub struct NisLz77Reader<R: Read> {
     inner: R,
     copy_buf: VecDeque<u8>,
@@ -80,7 +82,7 @@ impl<R> Read for NisLz77Reader<R>
     R: Read,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        while self.copy_buf.len() < 4095 + buf.len() && !self.eof {
+        while self.copy_buf.len() < 4096 + buf.len() && !self.eof {
             match self.next_cmd()? {
                 None => {
                     self.eof = true;
@@ -151,3 +153,67 @@ fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         Ok(bytes_read)
     }
 }
+
+/// Decodes a complete NIS LZ77 command stream into `dst`, which must be
+/// exactly the decompressed size.
+pub fn decompress_into(src: &[u8], dst: &mut [u8]) -> Result<(), DecodeError> {
+    let mut s = 0;
+    let mut d = 0;
+    let dst_len = dst.len() as u64;
+
+    while s < src.len() {
+        let cmd = src[s] as usize;
+        s += 1;
+
+        if cmd < 0x80 {
+            // Literal
+            let literal = src.get(s..s + cmd).ok_or(DecodeError::Truncated)?;
+            dst.get_mut(d..d + cmd)
+                .ok_or(DecodeError::OutputOverflow(dst_len))?
+                .copy_from_slice(literal);
+            s += cmd;
+            d += cmd;
+            continue;
+        }
+
+        let (len, off) = if cmd < 0xC0 {
+            // Short ptr
+            ((cmd >> 4) - 7, (cmd & 0x0F) + 1)
+        } else if cmd < 0xE0 {
+            // Long ptr
+            let r = *src.get(s).ok_or(DecodeError::Truncated)? as usize;
+            s += 1;
+            (cmd - 0xBE, r + 1)
+        } else {
+            // Longer ptr
+            let r = src.get(s..s + 2).ok_or(DecodeError::Truncated)?;
+            let (r0, r1) = (r[0] as usize, r[1] as usize);
+            s += 2;
+            (
+                ((cmd << 4) | (r0 >> 4)) - (0xE00 - 3),
+                ((r0 & 0x0F) << 8 | r1) + 1,
+            )
+        };
+
+        if off > d {
+            return Err(DecodeError::InvalidPointer);
+        }
+        if d + len > dst.len() {
+            return Err(DecodeError::OutputOverflow(dst_len));
+        }
+        if off >= len {
+            dst.copy_within(d - off..d - off + len, d);
+        } else {
+            // the source overlaps the bytes being written, so repeat them
+            for i in d..d + len {
+                dst[i] = dst[i - off];
+            }
+        }
+        d += len;
+    }
+
+    if d != dst.len() {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(())
+}
@@ -1,4 +1,42 @@
 mod compress;
 mod decompress;
+mod sniff;
 
-pub use self::decompress::Decoder;
+pub use self::compress::{
+    compress, compress_to_budget, compress_with, compress_with_level, CompressionLevel,
+    EncodeError, Encoder,
+};
+pub use self::decompress::{decompress_into, decompress_to_vec, DecodeError, Decoder};
+pub use self::sniff::MaybeCompressed;
+
+/// The payload encodings a YKCMP_V1 header can declare.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// Encoding 4, NIS' own LZ77 variant.
+    NisLz77,
+    /// Encoding 8, a raw LZ4 block.
+    Lz4V8,
+    /// Encoding 9, a raw LZ4 block. Its payload is the same as
+    /// [`Encoding::Lz4V8`]'s; only the id in the header differs, so
+    /// recompressed files can keep the id the original had.
+    Lz4V9,
+}
+
+impl Encoding {
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            4 => Some(Encoding::NisLz77),
+            8 => Some(Encoding::Lz4V8),
+            9 => Some(Encoding::Lz4V9),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            Encoding::NisLz77 => 4,
+            Encoding::Lz4V8 => 8,
+            Encoding::Lz4V9 => 9,
+        }
+    }
+}
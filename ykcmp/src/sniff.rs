@@ -0,0 +1,52 @@
+use std::io::{self, Chain, Cursor, Read};
+
+use crate::{DecodeError, Decoder};
+
+type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads a stream that may or may not be YKCMP compressed, decompressing it
+/// only if it starts with the YKCMP magic.
+pub struct MaybeCompressed<R: Read> {
+    inner: MaybeCompressedInner<R>,
+}
+
+enum MaybeCompressedInner<R: Read> {
+    Compressed(Decoder<Peeked<R>>),
+    Raw(Peeked<R>),
+}
+
+impl<R> MaybeCompressed<R>
+where
+    R: Read,
+{
+    pub fn new(mut inner: R) -> Result<Self, DecodeError> {
+        let mut magic = Vec::with_capacity(8);
+        (&mut inner).take(8).read_to_end(&mut magic)?;
+
+        let is_compressed = &magic[..] == b"YKCMP_V1";
+        let peeked = Cursor::new(magic).chain(inner);
+        let inner = if is_compressed {
+            MaybeCompressedInner::Compressed(Decoder::new(peeked)?)
+        } else {
+            MaybeCompressedInner::Raw(peeked)
+        };
+
+        Ok(MaybeCompressed { inner })
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.inner, MaybeCompressedInner::Compressed(_))
+    }
+}
+
+impl<R> Read for MaybeCompressed<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            MaybeCompressedInner::Compressed(ref mut decoder) => decoder.read(buf),
+            MaybeCompressedInner::Raw(ref mut inner) => inner.read(buf),
+        }
+    }
+}